@@ -10,7 +10,7 @@ use crate::ui_egui::{EguiUiState, WinGIFApp};
 use capture_wgc::{CaptureController, CaptureTarget, FrameProcessor, Rect};
 use crossbeam_channel::{bounded, Receiver, Sender};
 use eframe::egui;
-use export::{GifExportConfig, GifExporter};
+use export::{CancelToken, ExportConfig, ExportError, ProgressCallback};
 use overlay::{destroy_recording_outline, OverlayWindow, SelectionOutcome};
 use parking_lot::Mutex;
 use std::path::PathBuf;
@@ -22,7 +22,9 @@ use windows::Win32::System::WinRT::{RoInitialize, RoUninitialize, RO_INIT_MULTIT
 use windows::Win32::UI::HiDpi::{
     SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
 };
-use windows::Win32::UI::WindowsAndMessaging::{SetForegroundWindow, ShowWindow, SW_HIDE, SW_SHOW};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetWindowTextW, SetForegroundWindow, ShowWindow, SW_HIDE, SW_SHOW,
+};
 
 /// Capture worker commands
 enum CaptureCommand {
@@ -40,7 +42,11 @@ enum CaptureCommand {
 enum CaptureResult {
     Started,
     Progress { elapsed_secs: u64, frame_count: usize },
-    Stopped { frame_count: usize, duration_secs: f64 },
+    Stopped {
+        frame_count: usize,
+        duration_secs: f64,
+        timestamps: Vec<Duration>,
+    },
     Error(String),
 }
 
@@ -137,6 +143,13 @@ fn set_main_window_visible(ui_state: &Arc<Mutex<EguiUiState>>, visible: bool) {
     }
 }
 
+/// Title bar text of a window, if it has any
+fn window_title(hwnd: isize) -> Option<String> {
+    let mut buf = [0u16; 512];
+    let len = unsafe { GetWindowTextW(HWND(hwnd as *mut std::ffi::c_void), &mut buf) };
+    (len > 0).then(|| String::from_utf16_lossy(&buf[..len as usize]))
+}
+
 fn on_record_click(ui_state: Arc<Mutex<EguiUiState>>, cmd_tx: Sender<CaptureCommand>) {
     // Start selecting
     {
@@ -201,7 +214,8 @@ fn on_record_click(ui_state: Arc<Mutex<EguiUiState>>, cmd_tx: Sender<CaptureComm
                 std::fs::create_dir_all(&temp_dir).ok();
 
                 let capture_target = RecordingTarget::Window { hwnd };
-                let session = RecordingSession::new(capture_target.clone(), rect, temp_dir.clone(), 15);
+                let mut session = RecordingSession::new(capture_target.clone(), rect, temp_dir.clone(), 15);
+                session.source_window = window_title(hwnd);
 
                 {
                     let mut state = ui_state.lock();
@@ -324,10 +338,20 @@ fn on_stop_click(ui_state: Arc<Mutex<EguiUiState>>, cmd_tx: Sender<CaptureComman
 
 fn on_export_click(ui_state: Arc<Mutex<EguiUiState>>) {
     // Get frame paths
-    let (frame_paths, frame_count, duration_secs) = {
+    let (frame_paths, frame_count, duration_secs, mut capture, metadata) = {
         let state = ui_state.lock();
         if let Some(session) = state.state_machine.session() {
-            (session.all_frame_paths(), session.frame_count, session.duration_secs)
+            (
+                session.all_frame_paths(),
+                session.frame_count,
+                session.duration_secs,
+                session.capture_info(),
+                state
+                    .export_metadata
+                    .clone()
+                    .map(|m| session.embedded_metadata(m))
+                    .unwrap_or_default(),
+            )
         } else {
             return;
         }
@@ -340,11 +364,15 @@ fn on_export_click(ui_state: Arc<Mutex<EguiUiState>>) {
         return;
     }
 
-    // 过滤掉不存在的文件
-    let valid_frame_paths: Vec<PathBuf> = frame_paths
-        .into_iter()
-        .filter(|p| p.exists())
-        .collect();
+    // 过滤掉不存在的文件，时间戳随帧一起保留
+    let timestamps = std::mem::take(&mut capture.frame_timestamps);
+    let mut valid_frame_paths: Vec<PathBuf> = Vec::new();
+    for (i, path) in frame_paths.into_iter().enumerate() {
+        if path.exists() {
+            valid_frame_paths.push(path);
+            capture.frame_timestamps.extend(timestamps.get(i));
+        }
+    }
 
     if valid_frame_paths.is_empty() {
         let mut state = ui_state.lock();
@@ -357,10 +385,27 @@ fn on_export_click(ui_state: Arc<Mutex<EguiUiState>>) {
         eprintln!("警告: 预期 {} 帧，实际找到 {} 帧", frame_count, valid_frame_paths.len());
     }
 
+    let (exporter, format_id, options, sidecar, preset) = {
+        let state = ui_state.lock();
+        (
+            state.export_registry.get(&state.export_format),
+            state.export_format.clone(),
+            state.export_options.clone(),
+            state.export_sidecar,
+            state.export_preset,
+        )
+    };
+    let Some(exporter) = exporter else {
+        let mut state = ui_state.lock();
+        state.status_text = format!("未知的导出格式: {}", format_id);
+        return;
+    };
+
     // Show save dialog
+    let extensions = exporter.extensions();
     let output_path = rfd::FileDialog::new()
-        .add_filter("GIF 图像", &["gif"])
-        .set_file_name("recording.gif")
+        .add_filter(exporter.name(), extensions)
+        .set_file_name(format!("recording.{}", extensions.first().copied().unwrap_or("gif")))
         .save_file();
 
     let output_path = match output_path {
@@ -368,43 +413,84 @@ fn on_export_click(ui_state: Arc<Mutex<EguiUiState>>) {
         None => return,
     };
 
+    let mut fps = 15.0;
+    if duration_secs > 0.0 && frame_count > 0 {
+        fps = (frame_count as f64 / duration_secs).clamp(1.0, 60.0);
+    }
+
+    // 按分享预设检查并裁剪帧
+    let mut warnings = Vec::new();
+    if let Some(preset) = preset {
+        warnings = preset.check(capture.region.width, capture.region.height, capture.duration, fps);
+        if capture.frame_timestamps.len() != valid_frame_paths.len() {
+            capture.frame_timestamps = (0..valid_frame_paths.len())
+                .map(|i| Duration::from_secs_f64(i as f64 / fps))
+                .collect();
+        }
+        let selected = preset.select_frames(&capture.frame_timestamps);
+        valid_frame_paths = selected.iter().map(|&i| valid_frame_paths[i].clone()).collect();
+        capture.frame_timestamps = selected.iter().map(|&i| capture.frame_timestamps[i]).collect();
+        fps = fps.min(preset.max_fps as f64);
+
+        if valid_frame_paths.is_empty() {
+            let mut state = ui_state.lock();
+            state.status_text = "按预设裁剪后没有可导出的帧".to_string();
+            return;
+        }
+    }
+
+    let note = if warnings.is_empty() {
+        String::new()
+    } else {
+        let warnings: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+        format!(" ({})", warnings.join("; "))
+    };
+
     // Start exporting
+    let cancel = CancelToken::new();
     {
         let mut state = ui_state.lock();
         state.state_machine.start_exporting();
-        state.status_text = "导出中...".to_string();
+        state.status_text = format!("导出中...{}", note);
+        state.export_cancel = Some(cancel.clone());
     }
 
     // Export in background thread
     let ui_state_clone = ui_state.clone();
     thread::spawn(move || {
-        let mut fps = 15u8;
-        if duration_secs > 0.0 && frame_count > 0 {
-            let calc = (frame_count as f64 / duration_secs).round() as i32;
-            let clamped = calc.clamp(1, 60);
-            fps = clamped as u8;
-        }
-
-        let config = GifExportConfig {
+        let config = ExportConfig {
             output_path: output_path.clone(),
             fps,
-            quality: 90,
-            ..Default::default()
+            options,
+            capture: Some(capture),
+            sidecar,
+            metadata,
+            cancel,
         };
-
-        let result = GifExporter::export_from_pngs(&valid_frame_paths, config, None);
+        let progress_state = ui_state_clone.clone();
+        let progress_note = note.clone();
+        let progress: ProgressCallback = Box::new(move |progress: f32| {
+            let mut state = progress_state.lock();
+            state.status_text = format!("导出中... {:.0}%{}", progress * 100.0, progress_note);
+        });
+        let result = exporter.export(&valid_frame_paths, &config, Some(progress));
 
         let mut state = ui_state_clone.lock();
+        state.export_cancel = None;
         match result {
             Ok(_) => {
                 state.state_machine.finish_exporting();
-                state.status_text = format!("已导出: {}", output_path.display());
+                state.status_text = format!("已导出: {}{}", output_path.display(), note);
 
                 // Cleanup temp files
                 if let Some(session) = state.state_machine.session() {
                     let _ = std::fs::remove_dir_all(&session.temp_dir);
                 }
             }
+            Err(ExportError::Cancelled) => {
+                state.state_machine.cancel_exporting();
+                state.status_text = "已取消导出".to_string();
+            }
             Err(e) => {
                 state.state_machine.cancel_exporting();
                 state.status_text = format!("导出失败: {}", e);
@@ -470,6 +556,10 @@ fn capture_worker(cmd_rx: Receiver<CaptureCommand>, result_tx: Sender<CaptureRes
                 }
 
                 let frame_count = processor.as_ref().map(|p| p.frame_count()).unwrap_or(0);
+                let timestamps = processor
+                    .as_ref()
+                    .map(|p| p.timestamps().to_vec())
+                    .unwrap_or_default();
                 let duration_secs = start_time.map(|t| t.elapsed().as_secs_f64()).unwrap_or(0.0);
                 processor = None;
                 running = false;
@@ -478,6 +568,7 @@ fn capture_worker(cmd_rx: Receiver<CaptureCommand>, result_tx: Sender<CaptureRes
                 let _ = result_tx.send(CaptureResult::Stopped {
                     frame_count,
                     duration_secs,
+                    timestamps,
                 });
             }
             Ok(CaptureCommand::Shutdown) => {
@@ -539,12 +630,14 @@ fn result_handler(ui_state: Arc<Mutex<EguiUiState>>, result_rx: Receiver<Capture
             Ok(CaptureResult::Stopped {
                 frame_count,
                 duration_secs,
+                timestamps,
             }) => {
                 let mut state = ui_state.lock();
                 state.frame_count = frame_count;
                 if let Some(session) = state.state_machine.session_mut() {
                     session.frame_count = frame_count;
                     session.duration_secs = duration_secs;
+                    session.frame_timestamps = timestamps;
                 }
                 let secs = duration_secs.max(0.0).round() as u64;
                 state.status_text = format!("录制完成 ({}s)", secs);
@@ -1,7 +1,9 @@
 //! State machine for WinGIF
 
 use capture_wgc::Rect;
+use export::{CaptureInfo, CaptureRegion, CaptureTargetKind, EmbeddedMetadata};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
 /// Application state
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -59,8 +61,14 @@ pub struct RecordingSession {
     pub frame_count: usize,
     /// Recording duration in seconds
     pub duration_secs: f64,
+    /// Capture time of each frame, relative to the first
+    pub frame_timestamps: Vec<Duration>,
     /// FPS setting
     pub fps: u8,
+    /// When the session was started
+    pub started_at: SystemTime,
+    /// Title of the captured window
+    pub source_window: Option<String>,
 }
 
 /// Recording target type
@@ -81,7 +89,10 @@ impl RecordingSession {
             temp_dir,
             frame_count: 0,
             duration_secs: 0.0,
+            frame_timestamps: Vec::new(),
             fps,
+            started_at: SystemTime::now(),
+            source_window: None,
         }
     }
 
@@ -96,6 +107,34 @@ impl RecordingSession {
             .map(|i| self.frame_path(i))
             .collect()
     }
+
+    /// Recording details for the export metadata
+    pub fn capture_info(&self) -> CaptureInfo {
+        CaptureInfo {
+            target: match self.target {
+                RecordingTarget::Monitor { .. } => CaptureTargetKind::Monitor,
+                RecordingTarget::Window { .. } => CaptureTargetKind::Window,
+            },
+            region: CaptureRegion {
+                x: self.region.x,
+                y: self.region.y,
+                width: self.region.width,
+                height: self.region.height,
+            },
+            requested_fps: self.fps,
+            duration: Duration::from_secs_f64(self.duration_secs.max(0.0)),
+            frame_timestamps: self.frame_timestamps.clone(),
+        }
+    }
+
+    /// `metadata` completed with the source window and date of the recording
+    pub fn embedded_metadata(&self, metadata: EmbeddedMetadata) -> EmbeddedMetadata {
+        EmbeddedMetadata {
+            source_window: self.source_window.clone(),
+            capture_date: Some(self.started_at),
+            ..metadata
+        }
+    }
 }
 
 /// State machine transitions
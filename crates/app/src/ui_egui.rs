@@ -3,6 +3,10 @@
 use crate::state::{AppState, StateMachine};
 use overlay::{destroy_recording_outline, show_recording_outline};
 use eframe::egui;
+use export::{
+    CancelToken, EmbeddedMetadata, ExportOptions, ExportPreset, ExportRegistry, OptionKind, OptionSpec,
+    OptionValue, PRESETS,
+};
 use parking_lot::Mutex;
 use std::sync::Arc;
 
@@ -19,11 +23,25 @@ pub struct EguiUiState {
     pub on_record: Option<ActionCallback>,
     pub on_stop: Option<ActionCallback>,
     pub on_export: Option<ActionCallback>,
+    /// Available output formats
+    pub export_registry: Arc<ExportRegistry>,
+    /// Id of the selected output format
+    pub export_format: String,
+    /// Options for the selected format
+    pub export_options: ExportOptions,
+    /// Write a `<output>.json` metadata sidecar next to exports
+    pub export_sidecar: bool,
+    /// Sharing destination whose limits the export follows
+    pub export_preset: Option<&'static ExportPreset>,
+    /// Fields to embed in the exported file; `None` embeds nothing
+    pub export_metadata: Option<EmbeddedMetadata>,
+    /// Stops the running export
+    pub export_cancel: Option<CancelToken>,
 }
 
 impl EguiUiState {
     pub fn new() -> Self {
-        Self {
+        let mut state = Self {
             state_machine: StateMachine::new(),
             status_text: "就绪".to_string(),
             frame_count: 0,
@@ -32,7 +50,34 @@ impl EguiUiState {
             on_record: None,
             on_stop: None,
             on_export: None,
+            export_registry: Arc::new(ExportRegistry::default()),
+            export_format: String::new(),
+            export_options: ExportOptions::new(),
+            export_sidecar: false,
+            export_preset: None,
+            export_metadata: None,
+            export_cancel: None,
+        };
+        state.select_format("gif");
+        state
+    }
+
+    /// Select an output format and reset its options to their defaults
+    pub fn select_format(&mut self, id: &str) {
+        if let Some(exporter) = self.export_registry.get(id) {
+            self.export_options = ExportOptions::from_schema(&exporter.options());
+            self.export_format = id.to_string();
+        }
+        self.export_preset = None;
+    }
+
+    /// Select a sharing preset, switching to its format and limits
+    pub fn select_preset(&mut self, preset: Option<&'static ExportPreset>) {
+        if let Some(preset) = preset {
+            self.select_format(preset.format);
+            preset.apply(&mut self.export_options);
         }
+        self.export_preset = preset;
     }
 }
 
@@ -123,7 +168,7 @@ impl eframe::App for WinGIFApp {
         }
 
         // Clone necessary data to avoid holding lock during UI rendering
-        let (app_state, status_text, frame_count, on_record, on_stop, on_export) = {
+        let (app_state, status_text, frame_count, on_record, on_stop, on_export, export_cancel) = {
             let state = self.state.lock();
             (
                 state.state_machine.state().clone(),
@@ -132,8 +177,31 @@ impl eframe::App for WinGIFApp {
                 state.on_record.clone(),
                 state.on_stop.clone(),
                 state.on_export.clone(),
+                state.export_cancel.clone(),
             )
         };
+        let (
+            registry,
+            mut export_format,
+            mut export_options,
+            mut export_sidecar,
+            mut export_preset,
+            mut export_metadata,
+        ) = {
+            let state = self.state.lock();
+            (
+                state.export_registry.clone(),
+                state.export_format.clone(),
+                state.export_options.clone(),
+                state.export_sidecar,
+                state.export_preset,
+                state.export_metadata.clone(),
+            )
+        };
+        let old_sidecar = export_sidecar;
+        let old_preset = export_preset;
+        let old_metadata = export_metadata.clone();
+        let (old_format, old_options) = (export_format.clone(), export_options.clone());
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
@@ -200,7 +268,7 @@ impl eframe::App for WinGIFApp {
 
                     // Export button
                     let export_btn = egui::Button::new(
-                        egui::RichText::new("💾 导出 GIF")
+                        egui::RichText::new("💾 导出")
                             .size(16.0)
                             .color(egui::Color32::WHITE)
                     )
@@ -217,7 +285,96 @@ impl eframe::App for WinGIFApp {
                             callback();
                         }
                     }
+
+                    // Cancel button while an export is running
+                    if let (AppState::Exporting, Some(token)) = (&app_state, &export_cancel) {
+                        ui.add_space(15.0);
+                        let cancel_btn = egui::Button::new(
+                            egui::RichText::new("✖ 取消")
+                                .size(16.0)
+                                .color(egui::Color32::WHITE)
+                        )
+                        .fill(egui::Color32::from_rgb(108, 117, 125))
+                        .min_size(egui::vec2(120.0, 45.0))
+                        .rounding(8.0);
+
+                        if ui.add_enabled(!token.is_cancelled(), cancel_btn).clicked() {
+                            token.cancel();
+                        }
+                    }
+                });
+
+                ui.add_space(15.0);
+
+                // Export format and its options, listed from the registry
+                ui.horizontal(|ui| {
+                    ui.add_space(20.0);
+                    let selected = registry
+                        .get(&export_format)
+                        .map(|e| e.name())
+                        .unwrap_or_default();
+                    egui::ComboBox::from_label("导出格式")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for exporter in registry.formats() {
+                                ui.selectable_value(
+                                    &mut export_format,
+                                    exporter.id().to_string(),
+                                    exporter.name(),
+                                );
+                            }
+                        });
+                    ui.checkbox(&mut export_sidecar, "写入元数据 JSON");
+                });
+
+                // Sharing destination presets
+                ui.horizontal(|ui| {
+                    ui.add_space(20.0);
+                    egui::ComboBox::from_label("分享预设")
+                        .selected_text(export_preset.map_or("无", |p| p.name))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut export_preset, None, "无");
+                            for preset in PRESETS {
+                                ui.selectable_value(&mut export_preset, Some(preset), preset.name);
+                            }
+                        });
+                });
+
+                // Metadata written into the output file
+                ui.horizontal(|ui| {
+                    ui.add_space(20.0);
+                    let mut embed = export_metadata.is_some();
+                    if ui.checkbox(&mut embed, "嵌入元数据").changed() {
+                        export_metadata = embed.then(default_metadata);
+                    }
                 });
+                if let Some(metadata) = export_metadata.as_mut() {
+                    egui::Grid::new("export_metadata").num_columns(2).show(ui, |ui| {
+                        for (label, field) in [
+                            ("标题", &mut metadata.title),
+                            ("作者", &mut metadata.author),
+                            ("描述", &mut metadata.description),
+                        ] {
+                            ui.label(label);
+                            ui.text_edit_singleline(field.get_or_insert_with(String::new));
+                            ui.end_row();
+                        }
+                    });
+                }
+
+                if let Some(exporter) = registry.get(&old_format) {
+                    let specs = exporter.options();
+                    if !specs.is_empty() {
+                        egui::CollapsingHeader::new("导出选项").show(ui, |ui| {
+                            egui::Grid::new("export_options").num_columns(2).show(ui, |ui| {
+                                for spec in &specs {
+                                    option_widget(ui, spec, &mut export_options);
+                                    ui.end_row();
+                                }
+                            });
+                        });
+                    }
+                }
 
                 ui.add_space(25.0);
 
@@ -268,7 +425,58 @@ impl eframe::App for WinGIFApp {
             });
         });
 
+        if export_preset != old_preset {
+            self.state.lock().select_preset(export_preset);
+        } else if export_format != old_format {
+            self.state.lock().select_format(&export_format);
+        } else if export_options != old_options {
+            self.state.lock().export_options = export_options;
+        }
+        if export_sidecar != old_sidecar {
+            self.state.lock().export_sidecar = export_sidecar;
+        }
+        if export_metadata != old_metadata {
+            self.state.lock().export_metadata = export_metadata;
+        }
+
         // Request repaint for smooth animations
         ctx.request_repaint();
     }
 }
+
+/// Metadata to start from, with the Windows user as author
+fn default_metadata() -> EmbeddedMetadata {
+    EmbeddedMetadata {
+        author: std::env::var("USERNAME").ok(),
+        ..Default::default()
+    }
+}
+
+/// Edit one format option according to its schema entry
+fn option_widget(ui: &mut egui::Ui, spec: &OptionSpec, options: &mut ExportOptions) {
+    ui.label(spec.label);
+
+    let mut value = options
+        .get(spec.key)
+        .cloned()
+        .unwrap_or_else(|| spec.default_value());
+    match (&spec.kind, &mut value) {
+        (OptionKind::Bool { .. }, OptionValue::Bool(v)) => {
+            ui.checkbox(v, "");
+        }
+        (OptionKind::Int { min, max, .. }, OptionValue::Int(v)) => {
+            ui.add(egui::DragValue::new(v).range(*min..=*max));
+        }
+        (OptionKind::Choice { choices, .. }, OptionValue::Choice(v)) => {
+            egui::ComboBox::from_id_source(spec.key)
+                .selected_text(v.as_str())
+                .show_ui(ui, |ui| {
+                    for choice in choices.iter() {
+                        ui.selectable_value(v, choice.to_string(), *choice);
+                    }
+                });
+        }
+        _ => value = spec.default_value(),
+    }
+    options.set(spec.key, value);
+}
@@ -3,7 +3,7 @@
 use crate::{CaptureResult, Rect};
 use image::{ImageBuffer, RgbaImage};
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 /// Frame data from capture
 #[derive(Debug, Clone)]
@@ -64,6 +64,10 @@ pub struct FrameProcessor {
     output_dir: std::path::PathBuf,
     frame_count: usize,
     crop_rect: Option<Rect>,
+    /// Capture time of the first saved frame
+    first_timestamp: Option<Instant>,
+    /// Capture time of each saved frame, relative to the first
+    timestamps: Vec<Duration>,
 }
 
 impl FrameProcessor {
@@ -73,6 +77,8 @@ impl FrameProcessor {
             output_dir,
             frame_count: 0,
             crop_rect: None,
+            first_timestamp: None,
+            timestamps: Vec::new(),
         }
     }
 
@@ -95,6 +101,9 @@ impl FrameProcessor {
         frame_to_save.save_png(&path)?;
         self.frame_count += 1;
 
+        let first = *self.first_timestamp.get_or_insert(frame_to_save.timestamp);
+        self.timestamps.push(frame_to_save.timestamp.saturating_duration_since(first));
+
         Ok(path)
     }
 
@@ -103,6 +112,11 @@ impl FrameProcessor {
         self.frame_count
     }
 
+    /// Capture time of each saved frame, relative to the first
+    pub fn timestamps(&self) -> &[Duration] {
+        &self.timestamps
+    }
+
     /// Get all saved frame paths
     pub fn get_frame_paths(&self) -> Vec<std::path::PathBuf> {
         (0..self.frame_count)
@@ -113,5 +127,7 @@ impl FrameProcessor {
     /// Reset frame count
     pub fn reset(&mut self) {
         self.frame_count = 0;
+        self.first_timestamp = None;
+        self.timestamps.clear();
     }
 }
@@ -0,0 +1,288 @@
+//! Animated PNG export
+//!
+//! Writes full 24-bit color with an 8-bit alpha channel, so no quantization is needed.
+
+use crate::metadata::EmbeddedMetadata;
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, frame_time, resize_to};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use image::RgbaImage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// APNG export configuration
+#[derive(Debug, Clone)]
+pub struct ApngExportConfig {
+    pub output_path: PathBuf,
+    pub fps: u8,
+    /// Capture time of each frame, relative to the first; frames past its
+    /// end, or all frames when empty, follow `fps`
+    pub timestamps: Vec<Duration>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Written as tEXt/iTXt chunks
+    pub metadata: EmbeddedMetadata,
+}
+
+impl Default for ApngExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            timestamps: Vec::new(),
+            width: None,
+            height: None,
+            metadata: EmbeddedMetadata::default(),
+        }
+    }
+}
+
+impl ApngExportConfig {
+    /// Seconds frame `index` stays up, when capture timestamps are known
+    fn frame_delay(&self, index: usize) -> Option<f64> {
+        (!self.timestamps.is_empty()).then(|| {
+            frame_time(&self.timestamps, self.fps, index + 1)
+                - frame_time(&self.timestamps, self.fps, index)
+        })
+    }
+}
+
+fn apng_error(e: png::EncodingError) -> ExportError {
+    ExportError::ApngEncode(e.to_string())
+}
+
+/// Streaming APNG writer
+///
+/// The frame count has to be known before the header is written, so callers
+/// provide it up front.
+struct ApngWriter<W: Write> {
+    writer: png::Writer<W>,
+    width: u32,
+    height: u32,
+}
+
+impl<W: Write> ApngWriter<W> {
+    fn new(
+        inner: W,
+        width: u32,
+        height: u32,
+        frame_count: usize,
+        config: &ApngExportConfig,
+    ) -> ExportResult<Self> {
+        let mut encoder = png::Encoder::new(inner, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // 0 plays means loop forever
+        encoder.set_animated(frame_count as u32, 0).map_err(apng_error)?;
+        encoder.set_frame_delay(1, config.fps.max(1) as u16).map_err(apng_error)?;
+        config.metadata.add_png_text(&mut encoder).map_err(apng_error)?;
+
+        let writer = encoder.write_header().map_err(apng_error)?;
+        Ok(Self { writer, width, height })
+    }
+
+    /// Write the next frame, shown for `delay` seconds or the default 1/fps
+    fn write_frame(&mut self, image: RgbaImage, delay: Option<f64>) -> ExportResult<()> {
+        if let Some(delay) = delay {
+            let ms = (delay * 1000.0).round().clamp(0.0, u16::MAX as f64) as u16;
+            self.writer.set_frame_delay(ms, 1000).map_err(apng_error)?;
+        }
+        let image = resize_to(image, self.width, self.height);
+        self.writer.write_image_data(image.as_raw()).map_err(apng_error)?;
+        Ok(())
+    }
+
+    fn finish(self) -> ExportResult<()> {
+        self.writer.finish().map_err(apng_error)?;
+        Ok(())
+    }
+}
+
+/// APNG exporter
+pub struct ApngExporter {
+    config: ApngExportConfig,
+    frames: Option<Vec<RgbaImage>>,
+    /// Frames announced by [`with_frame_count`](Self::with_frame_count)
+    frame_count: Option<usize>,
+    writer: Option<ApngWriter<BufWriter<File>>>,
+    written: usize,
+}
+
+impl ApngExporter {
+    /// Create a new APNG exporter
+    pub fn new(config: ApngExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            frames: None,
+            frame_count: None,
+            writer: None,
+            written: 0,
+        })
+    }
+
+    /// Write frames as they arrive instead of holding them until
+    /// [`finish`](Self::finish); exactly `frame_count` must follow
+    pub fn with_frame_count(mut self, frame_count: usize) -> Self {
+        self.frame_count = Some(frame_count);
+        self
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        self.frames = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Add a frame to the animation
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        let frames = self.frames.as_mut()
+            .ok_or_else(|| ExportError::ApngEncode("Exporter not started".to_string()))?;
+        let Some(frame_count) = self.frame_count else {
+            frames.push(image);
+            return Ok(());
+        };
+
+        if self.writer.is_none() {
+            let (width, height) =
+                fit_dimensions(image.width(), image.height(), self.config.width, self.config.height);
+            self.writer = Some(Self::create_writer(&self.config, width, height, frame_count)?);
+        }
+        if let Some(ref mut writer) = self.writer {
+            writer.write_frame(image, self.config.frame_delay(self.written))?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        if let Some(writer) = self.writer.take() {
+            if Some(self.written) != self.frame_count {
+                return Err(ExportError::ApngEncode(format!(
+                    "{} of {} announced frames arrived",
+                    self.written,
+                    self.frame_count.unwrap_or_default()
+                )));
+            }
+            writer.finish()?;
+            return Ok(self.config.output_path.clone());
+        }
+
+        let frames = self.frames.take().unwrap_or_default();
+        let first = frames.first().ok_or(ExportError::NoFrames)?;
+
+        let (width, height) = fit_dimensions(
+            first.width(),
+            first.height(),
+            self.config.width,
+            self.config.height,
+        );
+
+        let file = BufWriter::new(File::create(&self.config.output_path)?);
+        let mut writer = ApngWriter::new(file, width, height, frames.len(), &self.config)?;
+        for (i, frame) in frames.into_iter().enumerate() {
+            writer.write_frame(frame, self.config.frame_delay(i))?;
+        }
+        writer.finish()?;
+
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to APNG
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: ApngExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let total = png_paths.len();
+        let mut writer: Option<ApngWriter<BufWriter<File>>> = None;
+
+        for (i, path) in png_paths.iter().enumerate() {
+            let img = image::open(path)?.to_rgba8();
+
+            if writer.is_none() {
+                let (width, height) =
+                    fit_dimensions(img.width(), img.height(), config.width, config.height);
+                writer = Some(Self::create_writer(&config, width, height, total)?);
+            }
+            if let Some(ref mut w) = writer {
+                w.write_frame(img, config.frame_delay(i))?;
+            }
+
+            if let Some(ref cb) = progress {
+                cb((i + 1) as f32 / total as f32);
+            }
+        }
+
+        if let Some(w) = writer {
+            w.finish()?;
+        }
+
+        Ok(config.output_path)
+    }
+
+    fn create_writer(
+        config: &ApngExportConfig,
+        width: u32,
+        height: u32,
+        frame_count: usize,
+    ) -> ExportResult<ApngWriter<BufWriter<File>>> {
+        let file = BufWriter::new(File::create(&config.output_path)?);
+        ApngWriter::new(file, width, height, frame_count, config)
+    }
+}
+
+/// APNG entry in the format registry
+pub(crate) struct ApngFormat;
+
+impl Exporter for ApngFormat {
+    fn id(&self) -> &'static str {
+        "apng"
+    }
+
+    fn name(&self) -> &'static str {
+        "APNG"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["apng", "png"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            alpha: true,
+            variable_delays: true,
+            max_colors: None,
+            lossy: false,
+            lossless: true,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        OptionSpec::size_limits().to_vec()
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let apng_config = ApngExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            timestamps: config.frame_timestamps(png_paths.len()),
+            width: config.options.dimension("width"),
+            height: config.options.dimension("height"),
+            metadata: config.metadata.clone(),
+        };
+        ApngExporter::export_from_pngs(png_paths, apng_config, progress)
+    }
+}
@@ -0,0 +1,146 @@
+//! Dithering for the native GIF encoder
+//!
+//! Only drawn pixels are dithered; holes neither receive nor pass on error.
+//! Ordered dithering is anchored to the canvas, so a static area keeps the
+//! same pattern from frame to frame.
+
+use super::quantize::ColorMap;
+use super::GifDither;
+
+/// 8x8 Bayer threshold matrix
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Error diffusion weights as `(dx, dy, weight)`, and their sum
+const FLOYD_STEINBERG: (&[(isize, usize, f32)], f32) =
+    (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+const SIERRA: (&[(isize, usize, f32)], f32) = (
+    &[
+        (1, 0, 5.0),
+        (2, 0, 3.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 5.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-1, 2, 2.0),
+        (0, 2, 3.0),
+        (1, 2, 2.0),
+    ],
+    32.0,
+);
+
+/// Rectangle of pixels placed on the canvas
+pub(crate) struct Block<'a> {
+    pub(crate) pixels: &'a [[u8; 3]],
+    pub(crate) drawn: &'a [bool],
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: usize,
+}
+
+/// Palette indices of `block`, with `hole` wherever a pixel isn't drawn
+///
+/// `strength` runs from 0 (plain nearest color) to 100. Frames whose colors
+/// all made it into the palette are never dithered.
+pub(crate) fn map_pixels(
+    block: &Block,
+    color_map: &mut ColorMap,
+    method: GifDither,
+    strength: u8,
+    hole: u8,
+) -> Vec<u8> {
+    let strength = strength.min(100) as f32 / 100.0;
+    let method = if color_map.is_exact() || strength == 0.0 {
+        GifDither::None
+    } else {
+        method
+    };
+
+    match method {
+        GifDither::None => block
+            .pixels
+            .iter()
+            .zip(block.drawn)
+            .map(|(&p, &d)| if d { color_map.index_of(p) } else { hole })
+            .collect(),
+        GifDither::Bayer => ordered(block, color_map, strength, hole),
+        GifDither::FloydSteinberg => diffuse(block, color_map, FLOYD_STEINBERG, strength, hole),
+        GifDither::Sierra => diffuse(block, color_map, SIERRA, strength, hole),
+    }
+}
+
+fn ordered(block: &Block, color_map: &mut ColorMap, strength: f32, hole: u8) -> Vec<u8> {
+    // Roughly the distance between neighboring palette colors
+    let spread = 255.0 / (color_map.palette.len() as f32).cbrt() * strength;
+
+    let mut out = Vec::with_capacity(block.pixels.len());
+    for (i, (&p, &d)) in block.pixels.iter().zip(block.drawn).enumerate() {
+        if !d {
+            out.push(hole);
+            continue;
+        }
+        let x = block.x as usize + i % block.width;
+        let y = block.y as usize + i / block.width;
+        let threshold = (BAYER[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5;
+        let offset = threshold * spread;
+        out.push(color_map.index_of(p.map(|v| (v as f32 + offset).clamp(0.0, 255.0) as u8)));
+    }
+    out
+}
+
+fn diffuse(
+    block: &Block,
+    color_map: &mut ColorMap,
+    (kernel, total): (&[(isize, usize, f32)], f32),
+    strength: f32,
+    hole: u8,
+) -> Vec<u8> {
+    let width = block.width;
+    let height = block.pixels.len() / width.max(1);
+    // Error carried into the current row and the two below it
+    let mut errors = vec![vec![[0.0f32; 3]; width]; 3];
+
+    let mut out = Vec::with_capacity(block.pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if !block.drawn[i] {
+                out.push(hole);
+                continue;
+            }
+
+            let error = errors[0][x];
+            let wanted: [f32; 3] =
+                std::array::from_fn(|c| (block.pixels[i][c] as f32 + error[c]).clamp(0.0, 255.0));
+            let index = color_map.index_of(wanted.map(|v| v.round() as u8));
+            out.push(index);
+
+            let got = color_map.palette[index as usize];
+            let residual: [f32; 3] =
+                std::array::from_fn(|c| (wanted[c] - got[c] as f32) * strength / total);
+            for &(dx, dy, weight) in kernel {
+                let Some(nx) = x.checked_add_signed(dx).filter(|&nx| nx < width) else {
+                    continue;
+                };
+                if y + dy >= height || !block.drawn[(y + dy) * width + nx] {
+                    continue;
+                }
+                for (e, r) in errors[dy][nx].iter_mut().zip(residual) {
+                    *e += r * weight;
+                }
+            }
+        }
+        errors.rotate_left(1);
+        errors[2].fill([0.0; 3]);
+    }
+    out
+}
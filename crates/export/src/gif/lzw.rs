@@ -0,0 +1,292 @@
+//! GIF flavoured LZW compression
+//!
+//! Variable-width codes from `min_code_size + 1` up to 12 bits, packed least
+//! significant bit first, with a clear code whenever the table fills up.
+//!
+//! In lossy mode a run may continue with a table entry whose next color is
+//! merely close to the pixel's, in the spirit of gifsicle's `--lossy`.
+
+const MAX_CODE_BITS: u8 = 12;
+const MAX_CODES: u16 = 1 << MAX_CODE_BITS;
+
+/// Open-addressed `(prefix, byte) -> code` table
+///
+/// Twice the maximum number of codes keeps probe chains short, and clearing
+/// it is a cheap fill.
+struct CodeTable {
+    keys: Vec<u32>,
+    codes: Vec<u16>,
+}
+
+impl CodeTable {
+    const SIZE: usize = 2 * MAX_CODES as usize;
+
+    fn new() -> Self {
+        Self {
+            keys: vec![0; Self::SIZE],
+            codes: vec![0; Self::SIZE],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.keys.fill(0);
+    }
+
+    /// Slot holding `key`, or the empty slot where it belongs
+    fn slot(&self, key: u32) -> usize {
+        let mut slot = (key.wrapping_mul(0x9E37_79B1) >> 19) as usize % Self::SIZE;
+        while self.keys[slot] != 0 && self.keys[slot] != key {
+            slot = (slot + 1) % Self::SIZE;
+        }
+        slot
+    }
+
+    fn get(&self, prefix: u16, byte: u8) -> Option<u16> {
+        let key = Self::key(prefix, byte);
+        let slot = self.slot(key);
+        (self.keys[slot] == key).then(|| self.codes[slot])
+    }
+
+    fn insert(&mut self, prefix: u16, byte: u8, code: u16) {
+        let key = Self::key(prefix, byte);
+        let slot = self.slot(key);
+        self.keys[slot] = key;
+        self.codes[slot] = code;
+    }
+
+    /// Non-zero key, so zero can mark empty slots
+    fn key(prefix: u16, byte: u8) -> u32 {
+        ((prefix as u32) << 8 | byte as u32) + 1
+    }
+}
+
+/// Packs codes LSB first
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// LZW encoder state for one image
+pub(crate) struct LzwEncoder {
+    min_code_size: u8,
+    table: CodeTable,
+    writer: BitWriter,
+    code_size: u8,
+    next_code: u16,
+    /// Per palette index, the other indices close enough to stand in for
+    /// it, nearest first; empty when lossless
+    similar: Vec<Vec<u8>>,
+}
+
+impl LzwEncoder {
+    pub(crate) fn new(min_code_size: u8) -> Self {
+        let mut encoder = Self {
+            min_code_size,
+            table: CodeTable::new(),
+            writer: BitWriter { out: Vec::new(), buffer: 0, bits: 0 },
+            code_size: 0,
+            next_code: 0,
+            similar: Vec::new(),
+        };
+        encoder.reset();
+        encoder
+    }
+
+    /// Let pixels be swapped for colors of `palette` within `max_distance`
+    /// (Euclidean RGB) when that extends a run; `keep` is never swapped
+    /// either way, e.g. the transparent index
+    pub(crate) fn lossy(mut self, palette: &[[u8; 3]], max_distance: u32, keep: Option<u8>) -> Self {
+        if max_distance == 0 {
+            return self;
+        }
+        let limit = max_distance * max_distance;
+        let distance = |a: [u8; 3], b: [u8; 3]| -> u32 {
+            (0..3).map(|c| (a[c] as i32 - b[c] as i32).pow(2) as u32).sum()
+        };
+        let fixed = |i: usize| keep == Some(i as u8);
+
+        self.similar = (0..palette.len())
+            .map(|i| {
+                if fixed(i) {
+                    return Vec::new();
+                }
+                let mut near: Vec<(u32, u8)> = (0..palette.len())
+                    .filter(|&j| j != i && !fixed(j))
+                    .map(|j| (distance(palette[i], palette[j]), j as u8))
+                    .filter(|&(d, _)| d <= limit)
+                    .collect();
+                near.sort_unstable();
+                near.into_iter().map(|(_, j)| j).collect()
+            })
+            .collect();
+        self
+    }
+
+    /// Code continuing `prefix` with `byte`, or with a color close to it
+    fn extend(&self, prefix: u16, byte: u8) -> Option<u16> {
+        self.table.get(prefix, byte).or_else(|| {
+            self.similar
+                .get(byte as usize)?
+                .iter()
+                .find_map(|&other| self.table.get(prefix, other))
+        })
+    }
+
+    fn clear_code(&self) -> u16 {
+        1 << self.min_code_size
+    }
+
+    /// Emit a clear code and start over with an empty table
+    fn reset(&mut self) {
+        self.writer.write(self.clear_code(), self.code_size.max(self.min_code_size + 1));
+        self.table.clear();
+        self.code_size = self.min_code_size + 1;
+        self.next_code = self.clear_code() + 2;
+    }
+
+    /// Emit `code`, widening the codes once the next table entry needs it
+    fn emit(&mut self, code: u16) {
+        self.writer.write(code, self.code_size);
+        if self.next_code >= 1 << self.code_size && self.code_size < MAX_CODE_BITS {
+            self.code_size += 1;
+        }
+    }
+
+    /// Compress palette indices into the raw LZW stream (without sub-blocks)
+    pub(crate) fn encode(mut self, indices: &[u8]) -> Vec<u8> {
+        let mut iter = indices.iter();
+        if let Some(&first) = iter.next() {
+            let mut prefix = first as u16;
+            for &byte in iter {
+                if let Some(code) = self.extend(prefix, byte) {
+                    prefix = code;
+                    continue;
+                }
+
+                self.emit(prefix);
+                if self.next_code >= MAX_CODES - 1 {
+                    self.reset();
+                } else {
+                    self.table.insert(prefix, byte, self.next_code);
+                    self.next_code += 1;
+                }
+                prefix = byte as u16;
+            }
+            self.emit(prefix);
+        }
+
+        let end = self.clear_code() + 1;
+        self.emit(end);
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The 10x10 three-color sample image walked through in most GIF format
+    /// write-ups, with the data giflib writes for it
+    const SAMPLE_INDICES: [&str; 10] = [
+        "1111122222",
+        "1111122222",
+        "1111122222",
+        "1110000222",
+        "1110000222",
+        "2220000111",
+        "2220000111",
+        "2222211111",
+        "2222211111",
+        "2222211111",
+    ];
+    const SAMPLE_DATA: [u8; 22] = [
+        0x8C, 0x2D, 0x99, 0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA, 0xA8,
+        0xDE, 0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01,
+    ];
+
+    fn sample_indices() -> Vec<u8> {
+        SAMPLE_INDICES.concat().bytes().map(|b| b - b'0').collect()
+    }
+
+    #[test]
+    fn matches_giflib_sample() {
+        assert_eq!(LzwEncoder::new(2).encode(&sample_indices()), SAMPLE_DATA);
+    }
+
+    /// Wrap `data` as the only image of a 256-color GIF and decode its indices
+    fn decode(data: &[u8], width: u16, height: u16) -> Vec<u8> {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&height.to_le_bytes());
+        gif.extend_from_slice(&[0xF7, 0, 0]);
+        gif.extend((0..=255u8).flat_map(|i| [i, i, i]));
+        gif.push(0x2C);
+        for v in [0, 0, width, height] {
+            gif.extend_from_slice(&v.to_le_bytes());
+        }
+        gif.extend_from_slice(&[0x00, 8]);
+        for block in data.chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.extend_from_slice(&[0x00, 0x3B]);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(gif.as_slice()).unwrap();
+        decoder.read_next_frame().unwrap().unwrap().buffer.to_vec()
+    }
+
+    #[test]
+    fn round_trips_across_table_resets() {
+        // Noisy enough to fill the 4096-entry table several times
+        let mut state = 1u32;
+        let indices: Vec<u8> = (0..256 * 256)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8 & 0x3F
+            })
+            .collect();
+        let data = LzwEncoder::new(8).encode(&indices);
+        assert_eq!(decode(&data, 256, 256), indices);
+    }
+
+    #[test]
+    fn lossy_only_swaps_close_colors() {
+        // Gray bands with one level of noise, which lossy mode may smooth over
+        let palette: Vec<[u8; 3]> = (0..=255u8).map(|i| [i, i, i]).collect();
+        let mut state = 7u32;
+        let indices: Vec<u8> = (0..128 * 64)
+            .map(|i| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                ((i % 128) / 16 * 32 + (state >> 16) % 2) as u8
+            })
+            .collect();
+        let data = LzwEncoder::new(8).lossy(&palette, 2, None).encode(&indices);
+        assert!(data.len() < LzwEncoder::new(8).encode(&indices).len());
+        // Neighbouring grays are sqrt(3) apart, the next ones already too far
+        for (a, b) in decode(&data, 128, 64).iter().zip(&indices) {
+            assert!(a.abs_diff(*b) <= 1);
+        }
+    }
+}
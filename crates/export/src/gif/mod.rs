@@ -0,0 +1,875 @@
+//! GIF export
+//!
+//! Two backends: a native MIT-licensed encoder, the default, and gifski
+//! (AGPL, behind the opt-in `gifski` feature).
+
+mod dither;
+mod lzw;
+mod native;
+mod optimize;
+mod quantize;
+mod target_size;
+
+use crate::cancel::CancelToken;
+use crate::metadata::{self, EmbeddedMetadata};
+use crate::registry::{
+    finish_export, ExportConfig, ExportOptions, Exporter, FormatCapabilities, OptionSpec,
+};
+use crate::util::frame_time;
+use crate::{ExportError, ExportResult, ProgressCallback};
+#[cfg(feature = "gifski")]
+use crossbeam_channel::{bounded, Receiver, Sender};
+#[cfg(feature = "gifski")]
+use gifski::{Collector, Settings, Writer};
+use image::RgbaImage;
+#[cfg(feature = "gifski")]
+use imgref::ImgVec;
+use native::NativeGifEncoder;
+pub use optimize::{optimize_gif, GifOptimizeOptions};
+pub use target_size::GifSizeFit;
+#[cfg(feature = "gifski")]
+use rgb::RGBA8;
+use std::fs::{self, File};
+use std::io::BufWriter;
+#[cfg(feature = "gifski")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+#[cfg(feature = "gifski")]
+use std::thread;
+
+/// GIF encoder implementation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GifBackend {
+    /// gifski, best quality; AGPL-licensed and only available with the
+    /// `gifski` feature
+    Gifski,
+    /// Built-in encoder with per-frame palettes and inter-frame
+    /// transparency; MIT-licensed like the rest of WinGIF
+    Native,
+}
+
+impl GifBackend {
+    /// Whether this backend was compiled in
+    pub fn is_available(self) -> bool {
+        match self {
+            Self::Gifski => cfg!(feature = "gifski"),
+            Self::Native => true,
+        }
+    }
+
+    fn id(self) -> &'static str {
+        match self {
+            Self::Gifski => "gifski",
+            Self::Native => "native",
+        }
+    }
+}
+
+impl Default for GifBackend {
+    /// The native encoder; gifski is only used when chosen explicitly
+    fn default() -> Self {
+        Self::Native
+    }
+}
+
+/// Palette quantizer of the native encoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GifQuantizer {
+    /// Median cut, fast and even
+    #[default]
+    MedianCut,
+    /// Xiaolin Wu's variance minimization, sharper on gradients
+    Wu,
+    /// NeuQuant neural network, good on photographic content but slower
+    NeuQuant,
+    /// Median cut refined with k-means, the slowest and most accurate
+    KMeans,
+}
+
+impl GifQuantizer {
+    fn id(self) -> &'static str {
+        match self {
+            Self::MedianCut => "median-cut",
+            Self::Wu => "wu",
+            Self::NeuQuant => "neuquant",
+            Self::KMeans => "kmeans",
+        }
+    }
+}
+
+/// Dithering method of the native encoder
+///
+/// Flat UI recordings look cleanest without dithering; gradients and video
+/// band less with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GifDither {
+    #[default]
+    None,
+    /// Ordered 8x8 Bayer pattern, stable between frames
+    Bayer,
+    /// Floyd–Steinberg error diffusion
+    FloydSteinberg,
+    /// Sierra error diffusion, smoother than Floyd–Steinberg
+    Sierra,
+}
+
+impl GifDither {
+    fn id(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Bayer => "bayer",
+            Self::FloydSteinberg => "floyd-steinberg",
+            Self::Sierra => "sierra",
+        }
+    }
+}
+
+/// How often a GIF plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GifRepeat {
+    #[default]
+    Infinite,
+    /// Play through once and stop on the last frame
+    Once,
+    /// Play this many times in total
+    Times(u16),
+}
+
+impl GifRepeat {
+    /// Plays after the first, as stored in the NETSCAPE2.0 extension;
+    /// `None` loops forever
+    fn extra_plays(self) -> Option<u16> {
+        match self {
+            Self::Infinite => None,
+            Self::Once => Some(0),
+            Self::Times(n) => Some(n.saturating_sub(1)),
+        }
+    }
+
+    #[cfg(feature = "gifski")]
+    fn to_gifski(self) -> gifski::Repeat {
+        match self.extra_plays() {
+            None => gifski::Repeat::Infinite,
+            Some(n) => gifski::Repeat::Finite(n),
+        }
+    }
+}
+
+/// Order the recorded frames are played in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GifPlayback {
+    #[default]
+    Forward,
+    /// Last frame first
+    Reverse,
+    /// Forward, then backward to the second frame, so a loop runs back
+    /// and forth without showing either end twice
+    PingPong,
+}
+
+impl GifPlayback {
+    fn id(self) -> &'static str {
+        match self {
+            Self::Forward => "forward",
+            Self::Reverse => "reverse",
+            Self::PingPong => "ping-pong",
+        }
+    }
+
+    /// Indices of `frame_count` recorded frames in playback order
+    fn order(self, frame_count: usize) -> Vec<usize> {
+        match self {
+            Self::Forward => (0..frame_count).collect(),
+            Self::Reverse => (0..frame_count).rev().collect(),
+            Self::PingPong => (0..frame_count)
+                .chain((1..frame_count.saturating_sub(1)).rev())
+                .collect(),
+        }
+    }
+}
+
+/// GIF export configuration
+#[derive(Debug, Clone)]
+pub struct GifExportConfig {
+    pub output_path: PathBuf,
+    pub fps: u8,
+    /// Capture time of each frame, relative to the first; frames past its
+    /// end, or all frames when empty, follow `fps`
+    pub timestamps: Vec<Duration>,
+    /// gifski quality, 1-100
+    pub quality: u8,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// gifski fast mode
+    pub fast: bool,
+    pub backend: GifBackend,
+    pub repeat: GifRepeat,
+    /// Frame order; anything but forward holds frames given to
+    /// [`GifExporter::add_frame`] in memory until `finish`
+    pub playback: GifPlayback,
+    /// Native encoder palette size per frame, 2-256
+    pub colors: u16,
+    /// Native encoder palette quantizer; gifski always uses its own
+    pub quantizer: GifQuantizer,
+    /// Native encoder dithering method
+    pub dither: GifDither,
+    /// Native encoder dithering strength, 0-100
+    pub dither_strength: u8,
+    /// Native encoder lossy LZW level, 0 (lossless) to 100; the largest
+    /// color error, in RGB units, accepted to lengthen a compressed run.
+    /// gifski derives its own lossy compression from `quality`
+    pub lossy: u8,
+    /// Written as comment and XMP extensions
+    pub metadata: EmbeddedMetadata,
+    /// Stops encoding; the partial file is removed and
+    /// [`ExportError::Cancelled`] returned
+    pub cancel: CancelToken,
+}
+
+impl Default for GifExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            timestamps: Vec::new(),
+            quality: 90,
+            width: None,
+            height: None,
+            fast: false,
+            backend: GifBackend::default(),
+            repeat: GifRepeat::default(),
+            playback: GifPlayback::default(),
+            colors: 256,
+            quantizer: GifQuantizer::default(),
+            dither: GifDither::default(),
+            dither_strength: 100,
+            lossy: 0,
+            metadata: EmbeddedMetadata::default(),
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+impl GifExportConfig {
+    /// Presentation time of frame `index` in seconds
+    fn frame_time(&self, index: usize) -> f64 {
+        frame_time(&self.timestamps, self.fps, index)
+    }
+
+    /// Config for the frames picked by `order`, each kept on screen as
+    /// long as it was recorded, played forward
+    ///
+    /// The timestamps get one entry past the last frame, marking when it
+    /// ends.
+    fn reordered(&self, order: &[usize]) -> Self {
+        let mut time = 0.0;
+        let mut timestamps = Vec::with_capacity(order.len() + 1);
+        for &i in order {
+            timestamps.push(Duration::from_secs_f64(time));
+            time += (self.frame_time(i + 1) - self.frame_time(i)).max(0.0);
+        }
+        timestamps.push(Duration::from_secs_f64(time));
+        Self {
+            timestamps,
+            playback: GifPlayback::Forward,
+            ..self.clone()
+        }
+    }
+
+    /// Add the metadata extensions to the finished file
+    fn embed_metadata(&self) -> ExportResult<()> {
+        if self.metadata.is_empty() {
+            return Ok(());
+        }
+        let data = fs::read(&self.output_path)?;
+        fs::write(&self.output_path, metadata::embed_in_gif(&data, &self.metadata)?)?;
+        Ok(())
+    }
+}
+
+/// Convert image::RgbaImage to imgref::ImgVec<RGBA8>
+#[cfg(feature = "gifski")]
+fn rgba_image_to_imgvec(img: RgbaImage) -> ImgVec<RGBA8> {
+    let width = img.width() as usize;
+    let height = img.height() as usize;
+    let raw = img.into_raw();
+
+    // Convert Vec<u8> to Vec<RGBA8>
+    let pixels: Vec<RGBA8> = raw
+        .chunks_exact(4)
+        .map(|chunk| RGBA8::new(chunk[0], chunk[1], chunk[2], chunk[3]))
+        .collect();
+
+    ImgVec::new(pixels, width, height)
+}
+
+/// Frame data for GIF export
+#[cfg(feature = "gifski")]
+pub struct GifFrame {
+    pub image: ImgVec<RGBA8>,
+    pub timestamp: f64,
+}
+
+/// Running gifski collector and writer threads
+#[cfg(feature = "gifski")]
+struct GifskiPipeline {
+    frame_sender: Sender<GifFrame>,
+    collector_handle: thread::JoinHandle<ExportResult<()>>,
+    writer_handle: thread::JoinHandle<ExportResult<()>>,
+}
+
+/// Reports each frame gifski writes and stops it once cancelled
+#[cfg(feature = "gifski")]
+struct GifskiProgress {
+    callback: Option<ProgressCallback>,
+    /// Frames expected, 0 if unknown
+    total: usize,
+    written: usize,
+    cancel: CancelToken,
+}
+
+#[cfg(feature = "gifski")]
+impl GifskiProgress {
+    fn report(&self, progress: f32) {
+        if let Some(ref cb) = self.callback {
+            cb(progress.min(1.0));
+        }
+    }
+}
+
+#[cfg(feature = "gifski")]
+impl gifski::progress::ProgressReporter for GifskiProgress {
+    /// Returning false makes gifski abort the write
+    fn increase(&mut self) -> bool {
+        self.written += 1;
+        if self.total > 0 {
+            self.report(self.written as f32 / self.total as f32);
+        }
+        !self.cancel.is_cancelled()
+    }
+
+    fn done(&mut self, _msg: &str) {}
+}
+
+/// Wait for the gifski threads; cancellation removes the partial file and
+/// takes precedence over the errors it caused
+#[cfg(feature = "gifski")]
+fn join_gifski(
+    collector_handle: thread::JoinHandle<ExportResult<()>>,
+    writer_handle: thread::JoinHandle<ExportResult<()>>,
+    config: &GifExportConfig,
+) -> ExportResult<()> {
+    let collected = collector_handle.join()
+        .map_err(|_| ExportError::GifEncode("Collector thread panicked".to_string()))?;
+    let written = writer_handle.join()
+        .map_err(|_| ExportError::GifEncode("Writer thread panicked".to_string()))?;
+    if config.cancel.is_cancelled() {
+        let _ = fs::remove_file(&config.output_path);
+        return Err(ExportError::Cancelled);
+    }
+    collected?;
+    written
+}
+
+/// Encoder state of the selected backend
+enum Pipeline {
+    #[cfg(feature = "gifski")]
+    Gifski(GifskiPipeline),
+    Native(NativeGifEncoder<BufWriter<File>>),
+}
+
+/// GIF exporter
+pub struct GifExporter {
+    config: GifExportConfig,
+    pipeline: Option<Pipeline>,
+    frame_count: usize,
+    /// Frames waiting to be reordered for playback
+    held: Vec<RgbaImage>,
+    /// Progress callback, moved to the writer thread by the gifski backend
+    progress: Option<ProgressCallback>,
+    /// Frames expected after playback reordering, 0 if unknown
+    expected_frames: usize,
+}
+
+impl GifExporter {
+    /// Create a new GIF exporter
+    pub fn new(config: GifExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            pipeline: None,
+            frame_count: 0,
+            held: Vec::new(),
+            progress: None,
+            expected_frames: 0,
+        })
+    }
+
+    /// Report progress while encoding `frame_count` input frames
+    ///
+    /// Must be called before [`start`](Self::start). Progress follows the
+    /// frames the encoder has written, reaching 1.0 once the file is done.
+    pub fn with_progress(mut self, callback: ProgressCallback, frame_count: usize) -> Self {
+        self.progress = Some(callback);
+        self.expected_frames = self.config.playback.order(frame_count).len();
+        self
+    }
+
+    /// Report the native encoder's progress after `written` frames
+    fn report_progress(&self, written: usize) {
+        if let (Some(cb), true) = (&self.progress, self.expected_frames > 0) {
+            cb((written as f32 / self.expected_frames as f32).min(1.0));
+        }
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        let pipeline = match self.config.backend {
+            #[cfg(feature = "gifski")]
+            GifBackend::Gifski => Pipeline::Gifski(self.start_gifski()?),
+            #[cfg(not(feature = "gifski"))]
+            GifBackend::Gifski => {
+                return Err(ExportError::GifEncode(
+                    "gifski backend is not included in this build".to_string(),
+                ));
+            }
+            GifBackend::Native => {
+                let file = BufWriter::new(File::create(&self.config.output_path)?);
+                Pipeline::Native(NativeGifEncoder::new(file, &self.config))
+            }
+        };
+        self.pipeline = Some(pipeline);
+        Ok(())
+    }
+
+    #[cfg(feature = "gifski")]
+    fn start_gifski(&mut self) -> ExportResult<GifskiPipeline> {
+        let settings = Settings {
+            width: self.config.width,
+            height: self.config.height,
+            quality: self.config.quality,
+            fast: self.config.fast,
+            repeat: self.config.repeat.to_gifski(),
+        };
+
+        let (collector, writer) = gifski::new(settings)
+            .map_err(|e| ExportError::GifEncode(e.to_string()))?;
+
+        let (frame_tx, frame_rx): (Sender<GifFrame>, Receiver<GifFrame>) = bounded(16);
+
+        // Collector thread
+        let collector_handle = thread::spawn(move || {
+            Self::collector_thread(collector, frame_rx)
+        });
+
+        // Writer thread; it trails the collector, so its frames are the progress
+        let output_path = self.config.output_path.clone();
+        let reporter = GifskiProgress {
+            callback: self.progress.take(),
+            total: self.expected_frames,
+            written: 0,
+            cancel: self.config.cancel.clone(),
+        };
+        let writer_handle = thread::spawn(move || {
+            Self::writer_thread(writer, &output_path, reporter)
+        });
+
+        Ok(GifskiPipeline {
+            frame_sender: frame_tx,
+            collector_handle,
+            writer_handle,
+        })
+    }
+
+    #[cfg(feature = "gifski")]
+    fn collector_thread(collector: Collector, frame_rx: Receiver<GifFrame>) -> ExportResult<()> {
+        let mut index = 0;
+        for frame in frame_rx {
+            collector.add_frame_rgba(index, frame.image, frame.timestamp)
+                .map_err(|e| ExportError::GifEncode(e.to_string()))?;
+            index += 1;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "gifski")]
+    fn writer_thread(
+        writer: Writer,
+        output_path: &Path,
+        mut reporter: GifskiProgress,
+    ) -> ExportResult<()> {
+        let file = File::create(output_path)?;
+        writer.write(file, &mut reporter)
+            .map_err(|e| ExportError::GifEncode(e.to_string()))?;
+        reporter.report(1.0);
+        Ok(())
+    }
+
+    /// Stop the encoder threads and remove the partial file
+    pub(crate) fn abort(&mut self) {
+        self.held.clear();
+        let Some(pipeline) = self.pipeline.take() else {
+            return;
+        };
+        match pipeline {
+            #[cfg(feature = "gifski")]
+            Pipeline::Gifski(gifski) => {
+                // The collector ends with the channel, the writer at its next frame
+                drop(gifski.frame_sender);
+                let _ = gifski.collector_handle.join();
+                let _ = gifski.writer_handle.join();
+            }
+            Pipeline::Native(encoder) => drop(encoder),
+        }
+        let _ = fs::remove_file(&self.config.output_path);
+    }
+
+    /// Abort if the export was cancelled
+    fn check_cancelled(&mut self) -> ExportResult<()> {
+        if self.config.cancel.is_cancelled() {
+            self.abort();
+            return Err(ExportError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Add a frame to the GIF
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        if self.pipeline.is_none() {
+            return Err(ExportError::GifEncode("Exporter not started".to_string()));
+        }
+        self.check_cancelled()?;
+
+        if self.config.playback == GifPlayback::Forward {
+            self.send_frame(image, self.frame_count)?;
+        } else {
+            // The order is only known once every frame is in
+            self.held.push(image);
+        }
+
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Hand frame `index` of the played sequence to the encoder
+    fn send_frame(&mut self, image: RgbaImage, index: usize) -> ExportResult<()> {
+        let pipeline = self.pipeline.as_mut()
+            .ok_or_else(|| ExportError::GifEncode("Exporter not started".to_string()))?;
+
+        let timestamp = self.config.frame_time(index);
+        match pipeline {
+            #[cfg(feature = "gifski")]
+            Pipeline::Gifski(gifski) => {
+                let imgvec = rgba_image_to_imgvec(image);
+                gifski.frame_sender.send(GifFrame { image: imgvec, timestamp })
+                    .map_err(|_| ExportError::GifEncode("Failed to send frame".to_string()))?;
+            }
+            Pipeline::Native(encoder) => {
+                encoder.add_frame(image, timestamp)?;
+                self.report_progress(index + 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        if self.frame_count == 0 {
+            return Err(ExportError::NoFrames);
+        }
+        self.check_cancelled()?;
+
+        if !self.held.is_empty() {
+            let held = std::mem::take(&mut self.held);
+            let order = self.config.playback.order(held.len());
+            self.config = self.config.reordered(&order);
+            for (index, &i) in order.iter().enumerate() {
+                self.check_cancelled()?;
+                self.send_frame(held[i].clone(), index)?;
+            }
+            self.frame_count = order.len();
+        }
+
+        match self.pipeline.take() {
+            #[cfg(feature = "gifski")]
+            Some(Pipeline::Gifski(gifski)) => {
+                // Drop sender to signal completion
+                drop(gifski.frame_sender);
+                join_gifski(gifski.collector_handle, gifski.writer_handle, &self.config)?;
+            }
+            Some(Pipeline::Native(encoder)) => {
+                let end = self.config.frame_time(self.frame_count);
+                encoder.finish(end)?;
+            }
+            None => return Err(ExportError::GifEncode("Exporter not started".to_string())),
+        }
+
+        self.config.embed_metadata()?;
+        self.report_progress(self.expected_frames);
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to GIF
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: GifExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let order = config.playback.order(png_paths.len());
+        let png_paths: Vec<PathBuf> = order.iter().map(|&i| png_paths[i].clone()).collect();
+        let png_paths = png_paths.as_slice();
+        let config = config.reordered(&order);
+
+        match config.backend {
+            #[cfg(feature = "gifski")]
+            GifBackend::Gifski => Self::export_with_gifski(png_paths, config, progress),
+            _ => {
+                let mut exporter = Self::new(config)?;
+                if let Some(cb) = progress {
+                    exporter = exporter.with_progress(cb, png_paths.len());
+                }
+                exporter.start()?;
+
+                for path in png_paths {
+                    let img = image::open(path)?.to_rgba8();
+                    exporter.add_frame(img)?;
+                }
+
+                exporter.finish()
+            }
+        }
+    }
+
+    #[cfg(feature = "gifski")]
+    fn export_with_gifski(
+        png_paths: &[PathBuf],
+        config: GifExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let settings = Settings {
+            width: config.width,
+            height: config.height,
+            quality: config.quality,
+            fast: config.fast,
+            repeat: config.repeat.to_gifski(),
+        };
+
+        let (collector, writer) = gifski::new(settings)
+            .map_err(|e| ExportError::GifEncode(e.to_string()))?;
+
+        let total = png_paths.len();
+        let times: Vec<f64> = (0..total).map(|i| config.frame_time(i)).collect();
+        let paths = png_paths.to_vec();
+
+        // Collector thread
+        let cancel = config.cancel.clone();
+        let collector_handle = thread::spawn(move || -> ExportResult<()> {
+            for (i, path) in paths.iter().enumerate() {
+                if cancel.is_cancelled() {
+                    return Err(ExportError::Cancelled);
+                }
+                let img = image::open(path)?.to_rgba8();
+                let imgvec = rgba_image_to_imgvec(img);
+                collector.add_frame_rgba(i, imgvec, times[i])
+                    .map_err(|e| ExportError::GifEncode(e.to_string()))?;
+            }
+            Ok(())
+        });
+
+        // Writer thread; it trails the collector, so its frames are the progress
+        let output_path = config.output_path.clone();
+        let mut reporter = GifskiProgress {
+            callback: progress,
+            total,
+            written: 0,
+            cancel: config.cancel.clone(),
+        };
+        let writer_handle = thread::spawn(move || -> ExportResult<()> {
+            let file = File::create(&output_path)?;
+            writer.write(file, &mut reporter)
+                .map_err(|e| ExportError::GifEncode(e.to_string()))?;
+            reporter.report(1.0);
+            Ok(())
+        });
+
+        join_gifski(collector_handle, writer_handle, &config)?;
+        config.embed_metadata()?;
+        Ok(config.output_path)
+    }
+}
+
+/// GIF entry in the format registry
+///
+/// With `max_size_kb` set, the sidecar records the settings the fit chose,
+/// and a file that can't be brought under the limit is removed.
+pub(crate) struct GifFormat;
+
+impl Exporter for GifFormat {
+    fn id(&self) -> &'static str {
+        "gif"
+    }
+
+    fn name(&self) -> &'static str {
+        "GIF"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gif"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            // Only fully transparent or opaque pixels
+            alpha: false,
+            variable_delays: true,
+            max_colors: Some(256),
+            lossy: true,
+            lossless: false,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = GifExportConfig::default();
+        let backends: &'static [&'static str] = if cfg!(feature = "gifski") {
+            &["native", "gifski"]
+        } else {
+            &["native"]
+        };
+        let mut options = vec![
+            OptionSpec::choice("backend", "Encoder", backends, defaults.backend.id()),
+            OptionSpec::int("quality", "Quality", 1, 100, defaults.quality as i64),
+            OptionSpec::bool("fast", "Fast encoding", defaults.fast),
+            OptionSpec::int("loops", "Loops (0 = forever)", 0, u16::MAX as i64, 0),
+            OptionSpec::choice(
+                "playback",
+                "Playback",
+                &["forward", "reverse", "ping-pong"],
+                defaults.playback.id(),
+            ),
+            OptionSpec::int("colors", "Colors (native)", 2, 256, defaults.colors as i64),
+            OptionSpec::choice(
+                "quantizer",
+                "Palette (native)",
+                &["median-cut", "wu", "neuquant", "kmeans"],
+                defaults.quantizer.id(),
+            ),
+            OptionSpec::choice(
+                "dither",
+                "Dithering (native)",
+                &["none", "bayer", "floyd-steinberg", "sierra"],
+                defaults.dither.id(),
+            ),
+            OptionSpec::int(
+                "dither_strength",
+                "Dither strength",
+                0,
+                100,
+                defaults.dither_strength as i64,
+            ),
+            OptionSpec::int("lossy", "Lossy LZW (native, 0 = off)", 0, 100, defaults.lossy as i64),
+            OptionSpec::int("max_size_kb", "Max file size (KB, 0 = off)", 0, 10_000_000, 0),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        self.encode_fitted(png_paths, config, progress).map(|(output, _)| output)
+    }
+
+    /// Like the provided export, but the sidecar records the settings a
+    /// size fit settled on
+    fn export(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let (output, options) = self.encode_fitted(png_paths, config, progress)?;
+        finish_export(self.id(), output, png_paths.len(), config, &options)
+    }
+}
+
+impl GifFormat {
+    /// Encode and return the output with the options actually used
+    ///
+    /// With `max_size_kb` set these are the compression, size and
+    /// `frame_step` the fit settled on. A result still over the limit is
+    /// deleted and reported as an error.
+    fn encode_fitted(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<(PathBuf, ExportOptions)> {
+        let defaults = GifExportConfig::default();
+        let options = &config.options;
+        let gif_config = GifExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            timestamps: config.frame_timestamps(png_paths.len()),
+            quality: options.u8_or("quality", defaults.quality),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+            fast: options.bool_or("fast", defaults.fast),
+            backend: match options.choice_or("backend", defaults.backend.id()) {
+                "gifski" => GifBackend::Gifski,
+                "native" => GifBackend::Native,
+                _ => defaults.backend,
+            },
+            repeat: match options.int_or("loops", 0).clamp(0, u16::MAX as i64) as u16 {
+                0 => GifRepeat::Infinite,
+                1 => GifRepeat::Once,
+                n => GifRepeat::Times(n),
+            },
+            playback: match options.choice_or("playback", defaults.playback.id()) {
+                "reverse" => GifPlayback::Reverse,
+                "ping-pong" => GifPlayback::PingPong,
+                _ => GifPlayback::Forward,
+            },
+            colors: options.int_or("colors", defaults.colors as i64).clamp(2, 256) as u16,
+            quantizer: match options.choice_or("quantizer", defaults.quantizer.id()) {
+                "wu" => GifQuantizer::Wu,
+                "neuquant" => GifQuantizer::NeuQuant,
+                "kmeans" => GifQuantizer::KMeans,
+                _ => GifQuantizer::MedianCut,
+            },
+            dither: match options.choice_or("dither", defaults.dither.id()) {
+                "bayer" => GifDither::Bayer,
+                "floyd-steinberg" => GifDither::FloydSteinberg,
+                "sierra" => GifDither::Sierra,
+                _ => GifDither::None,
+            },
+            dither_strength: options.u8_or("dither_strength", defaults.dither_strength),
+            lossy: options.u8_or("lossy", defaults.lossy),
+            metadata: config.metadata.clone(),
+            cancel: config.cancel.clone(),
+        };
+
+        let max_kb = options.int_or("max_size_kb", 0).max(0) as u64;
+        if max_kb == 0 {
+            let output = GifExporter::export_from_pngs(png_paths, gif_config, progress)?;
+            return Ok((output, options.clone()));
+        }
+        let fit = GifExporter::export_to_size(png_paths, gif_config, max_kb * 1024, progress)?;
+        if !fit.fits {
+            let _ = fs::remove_file(&fit.path);
+            return Err(ExportError::GifEncode(format!(
+                "smallest result is {} KB, over the {} KB limit",
+                fit.size.div_ceil(1024),
+                max_kb
+            )));
+        }
+        Ok((fit.path.clone(), fit.options(options)))
+    }
+}
@@ -0,0 +1,462 @@
+//! Native GIF encoder
+//!
+//! MIT-licensed alternative to gifski. Every frame gets its own local
+//! palette, and pixels that did not change since the previous frame are left
+//! transparent so only the changed rectangle is stored. From the first frame
+//! with transparent pixels on, frames are stored whole instead.
+
+use super::dither::{self, Block};
+use super::lzw::LzwEncoder;
+use super::quantize::ColorMap;
+use super::{GifDither, GifExportConfig, GifQuantizer, GifRepeat};
+use crate::util::{fit_dimensions, resize_to};
+use crate::{ExportError, ExportResult};
+use image::RgbaImage;
+use std::io::Write;
+
+/// Shortest delay browsers honor; anything below is played at 10 cs
+pub(crate) const MIN_DELAY_CS: u64 = 2;
+
+/// What the decoder does with a frame before drawing the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Disposal {
+    /// Leave it in place, the next frame draws on top
+    Keep = 1,
+    /// Clear its rectangle to transparent
+    Background = 2,
+}
+
+/// Sub-rectangle of the canvas
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Encoded frame waiting for the next one to know its delay
+struct PendingFrame {
+    /// Image descriptor, local color table and image data
+    image: Vec<u8>,
+    /// Presentation time in centiseconds
+    start_cs: u64,
+    disposal: Disposal,
+    transparent_index: Option<u8>,
+}
+
+/// Streaming GIF writer
+pub(crate) struct NativeGifEncoder<W: Write> {
+    writer: W,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    /// Palette size per frame, 2-256
+    colors: usize,
+    quantizer: GifQuantizer,
+    dither: GifDither,
+    dither_strength: u8,
+    lossy: u8,
+    repeat: GifRepeat,
+    /// Canvas size, known once the first frame arrives
+    size: Option<(u32, u32)>,
+    /// Set by the first frame with transparent pixels; from then on frames
+    /// are stored whole and clear themselves, since unchanged pixels can't
+    /// be told from holes
+    keeps_alpha: bool,
+    /// Source pixels of the last written frame
+    previous: Option<RgbaImage>,
+    pending: Option<PendingFrame>,
+}
+
+impl<W: Write> NativeGifEncoder<W> {
+    /// Encoder sized and quantized as in `config`
+    pub(crate) fn new(writer: W, config: &GifExportConfig) -> Self {
+        Self {
+            writer,
+            max_width: config.width,
+            max_height: config.height,
+            colors: config.colors.clamp(2, 256) as usize,
+            quantizer: config.quantizer,
+            dither: config.dither,
+            dither_strength: config.dither_strength,
+            lossy: config.lossy,
+            repeat: config.repeat,
+            size: None,
+            keeps_alpha: false,
+            previous: None,
+            pending: None,
+        }
+    }
+
+    /// Add a frame shown from `timestamp` seconds on
+    ///
+    /// Frames arriving sooner than the shortest honored delay after the
+    /// previous one are dropped.
+    pub(crate) fn add_frame(&mut self, image: RgbaImage, timestamp: f64) -> ExportResult<()> {
+        let start_cs = centiseconds(timestamp);
+        if let Some(pending) = &self.pending {
+            if start_cs < pending.start_cs + MIN_DELAY_CS {
+                return Ok(());
+            }
+        }
+
+        let (width, height) = match self.size {
+            Some(size) => size,
+            None => self.write_header(&image)?,
+        };
+        let image = resize_to(image, width, height);
+        if !self.keeps_alpha && image.pixels().any(|p| p[3] < 128) {
+            self.keeps_alpha = true;
+            self.redraw_pending_in_full();
+        }
+
+        let changed = match &self.previous {
+            Some(previous) if !self.keeps_alpha => changed_pixels(previous, &image),
+            _ => vec![true; (width * height) as usize],
+        };
+        let Some(area) = bounding_box(&changed, width) else {
+            // Identical to the previous frame, which simply stays up longer
+            return Ok(());
+        };
+
+        let frame = self.encode_frame(&image, &changed, area);
+        let disposal = if self.keeps_alpha {
+            Disposal::Background
+        } else {
+            Disposal::Keep
+        };
+        self.flush_pending(start_cs)?;
+        self.pending = Some(PendingFrame {
+            image: frame.0,
+            start_cs,
+            disposal,
+            transparent_index: frame.1,
+        });
+        self.previous = Some(image);
+        Ok(())
+    }
+
+    /// Write the last frame, shown until `end` seconds, and the trailer
+    pub(crate) fn finish(mut self, end: f64) -> ExportResult<W> {
+        let Some(pending) = &self.pending else {
+            return Err(ExportError::NoFrames);
+        };
+        let end_cs = centiseconds(end).max(pending.start_cs + MIN_DELAY_CS);
+        self.flush_pending(end_cs)?;
+        self.writer.write_all(&[0x3B])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Header, logical screen and looping extension, sized after `first`
+    fn write_header(&mut self, first: &RgbaImage) -> ExportResult<(u32, u32)> {
+        let (width, height) =
+            fit_dimensions(first.width(), first.height(), self.max_width, self.max_height);
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(ExportError::GifEncode(format!(
+                "{}x{} exceeds the GIF size limit",
+                width, height
+            )));
+        }
+
+        let w = &mut self.writer;
+        w.write_all(b"GIF89a")?;
+        w.write_all(&(width as u16).to_le_bytes())?;
+        w.write_all(&(height as u16).to_le_bytes())?;
+        // No global color table, background index 0, square pixels
+        w.write_all(&[0x00, 0x00, 0x00])?;
+        // NETSCAPE2.0 looping extension, 0 repeats forever; without it the
+        // animation plays once
+        let repeats = self.repeat.extra_plays();
+        if repeats != Some(0) {
+            w.write_all(&[0x21, 0xFF, 0x0B])?;
+            w.write_all(b"NETSCAPE2.0")?;
+            w.write_all(&[0x03, 0x01])?;
+            w.write_all(&repeats.unwrap_or(0).to_le_bytes())?;
+            w.write_all(&[0x00])?;
+        }
+
+        self.size = Some((width, height));
+        Ok((width, height))
+    }
+
+    /// Store the pending frame whole and clearing itself, so the frame
+    /// after it starts on an empty canvas
+    fn redraw_pending_in_full(&mut self) {
+        let (Some(mut pending), Some(previous)) = (self.pending.take(), self.previous.as_ref()) else {
+            return;
+        };
+        let (width, height) = previous.dimensions();
+        let all = vec![true; (width * height) as usize];
+        let area = Area { x: 0, y: 0, width, height };
+        (pending.image, pending.transparent_index) = self.encode_frame(previous, &all, area);
+        pending.disposal = Disposal::Background;
+        self.pending = Some(pending);
+    }
+
+    /// Image descriptor, local color table and LZW data of `area`
+    fn encode_frame(
+        &self,
+        image: &RgbaImage,
+        changed: &[bool],
+        area: Area,
+    ) -> (Vec<u8>, Option<u8>) {
+        let width = image.width();
+        let mut pixels = Vec::with_capacity((area.width * area.height) as usize);
+        let mut drawn = Vec::with_capacity(pixels.capacity());
+        for y in area.y..area.y + area.height {
+            for x in area.x..area.x + area.width {
+                let p = image.get_pixel(x, y).0;
+                let hole = self.keeps_alpha && p[3] < 128;
+                pixels.push([p[0], p[1], p[2]]);
+                drawn.push(changed[(y * width + x) as usize] && !hole);
+            }
+        }
+
+        let needs_transparency = drawn.iter().any(|&d| !d);
+        let visible: Vec<[u8; 3]> = pixels
+            .iter()
+            .zip(&drawn)
+            .filter(|(_, &d)| d)
+            .map(|(p, _)| *p)
+            .collect();
+        let max_colors = if needs_transparency {
+            self.colors - 1
+        } else {
+            self.colors
+        };
+        let mut color_map = ColorMap::new(&visible, max_colors, self.quantizer);
+
+        let mut palette = color_map.palette.clone();
+        let transparent_index = needs_transparency.then(|| {
+            palette.push([0, 0, 0]);
+            (palette.len() - 1) as u8
+        });
+        let block = Block {
+            pixels: &pixels,
+            drawn: &drawn,
+            x: area.x,
+            y: area.y,
+            width: area.width as usize,
+        };
+        let indices = dither::map_pixels(
+            &block,
+            &mut color_map,
+            self.dither,
+            self.dither_strength,
+            transparent_index.unwrap_or(0),
+        );
+
+        // Table size is 2^(bits), at least 2 entries
+        let bits = (palette.len().max(2) as u32).next_power_of_two().trailing_zeros() as u8;
+        let min_code_size = bits.max(2);
+
+        let mut out = Vec::new();
+        out.push(0x2C);
+        for v in [area.x, area.y, area.width, area.height] {
+            out.extend_from_slice(&(v as u16).to_le_bytes());
+        }
+        // Local color table, not interlaced
+        out.push(0x80 | (bits - 1));
+        for i in 0..1usize << bits {
+            out.extend_from_slice(&palette.get(i).copied().unwrap_or([0; 3]));
+        }
+
+        out.push(min_code_size);
+        let data = LzwEncoder::new(min_code_size)
+            .lossy(&palette, self.lossy as u32, transparent_index)
+            .encode(&indices);
+        for block in data.chunks(255) {
+            out.push(block.len() as u8);
+            out.extend_from_slice(block);
+        }
+        out.push(0x00);
+
+        (out, transparent_index)
+    }
+
+    /// Write the pending frame, now that the next one starts at `next_cs`
+    fn flush_pending(&mut self, next_cs: u64) -> ExportResult<()> {
+        let Some(frame) = self.pending.take() else {
+            return Ok(());
+        };
+        let delay = (next_cs - frame.start_cs).min(u16::MAX as u64) as u16;
+
+        let mut packed = (frame.disposal as u8) << 2;
+        if frame.transparent_index.is_some() {
+            packed |= 0x01;
+        }
+        let w = &mut self.writer;
+        w.write_all(&[0x21, 0xF9, 0x04, packed])?;
+        w.write_all(&delay.to_le_bytes())?;
+        w.write_all(&[frame.transparent_index.unwrap_or(0), 0x00])?;
+        w.write_all(&frame.image)?;
+        Ok(())
+    }
+}
+
+fn centiseconds(seconds: f64) -> u64 {
+    (seconds.max(0.0) * 100.0).round() as u64
+}
+
+/// Pixels whose color or alpha differs between the frames
+fn changed_pixels(previous: &RgbaImage, current: &RgbaImage) -> Vec<bool> {
+    previous
+        .pixels()
+        .zip(current.pixels())
+        .map(|(a, b)| a != b)
+        .collect()
+}
+
+/// Smallest rectangle containing every changed pixel
+fn bounding_box(changed: &[bool], width: u32) -> Option<Area> {
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (row, line) in changed.chunks(width as usize).enumerate() {
+        let Some(first) = line.iter().position(|&c| c) else {
+            continue;
+        };
+        let last = line.iter().rposition(|&c| c).unwrap_or(first);
+        left = left.min(first as u32);
+        right = right.max(last as u32);
+        top = top.min(row as u32);
+        bottom = row as u32;
+    }
+
+    (left != u32::MAX).then(|| Area {
+        x: left,
+        y: top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(repeat: GifRepeat) -> GifExportConfig {
+        GifExportConfig {
+            colors: 256,
+            dither: GifDither::None,
+            lossy: 0,
+            repeat,
+            ..Default::default()
+        }
+    }
+
+    /// Flat-colored frame with a square of `color` at `offset`
+    fn frame(offset: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_fn(48, 32, |x, y| {
+            if (offset..offset + 8).contains(&x) && (8..16).contains(&y) {
+                image::Rgba(color)
+            } else {
+                image::Rgba([(x / 12 * 60) as u8, (y / 8 * 60) as u8, 120, 255])
+            }
+        })
+    }
+
+    fn encode(frames: &[(RgbaImage, f64)], end: f64, repeat: GifRepeat) -> Vec<u8> {
+        let mut encoder = NativeGifEncoder::new(Vec::new(), &config(repeat));
+        for (image, timestamp) in frames {
+            encoder.add_frame(image.clone(), *timestamp).unwrap();
+        }
+        encoder.finish(end).unwrap()
+    }
+
+    /// Every frame as displayed, its delay in centiseconds, and the looping
+    fn decode(data: &[u8]) -> (Vec<RgbaImage>, Vec<u16>, gif::Repeat) {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(data).unwrap();
+        let mut canvas = RgbaImage::new(decoder.width() as u32, decoder.height() as u32);
+        let (mut shown, mut delays) = (Vec::new(), Vec::new());
+
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            let (left, top) = (frame.left as u32, frame.top as u32);
+            let width = frame.width as u32;
+            for (i, p) in (0..).zip(frame.buffer.chunks_exact(4)) {
+                let (x, y) = (left + i % width, top + i / width);
+                if p[3] != 0 {
+                    canvas.put_pixel(x, y, image::Rgba([p[0], p[1], p[2], p[3]]));
+                }
+            }
+            shown.push(canvas.clone());
+            delays.push(frame.delay);
+
+            if frame.dispose == gif::DisposalMethod::Background {
+                for y in top..top + frame.height as u32 {
+                    for x in left..left + width {
+                        canvas.put_pixel(x, y, image::Rgba([0, 0, 0, 0]));
+                    }
+                }
+            }
+        }
+        (shown, delays, decoder.repeat())
+    }
+
+    /// Equal up to the color of fully transparent pixels
+    fn assert_same(decoded: &RgbaImage, expected: &RgbaImage) {
+        for (a, b) in decoded.pixels().zip(expected.pixels()) {
+            if b[3] < 128 {
+                assert_eq!(a[3], 0);
+            } else {
+                assert_eq!(a.0, [b[0], b[1], b[2], 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_pixels_and_delays() {
+        let frames = [
+            (frame(0, [255, 0, 0, 255]), 0.0),
+            (frame(8, [255, 0, 0, 255]), 0.1),
+            (frame(8, [0, 255, 0, 255]), 0.15),
+            (frame(30, [0, 0, 255, 255]), 0.45),
+        ];
+        let (shown, delays, repeat) = decode(&encode(&frames, 0.5, GifRepeat::Infinite));
+
+        assert_eq!(delays, [10, 5, 30, 5]);
+        assert_eq!(repeat, gif::Repeat::Infinite);
+        for (decoded, (expected, _)) in shown.iter().zip(&frames) {
+            assert_same(decoded, expected);
+        }
+    }
+
+    #[test]
+    fn merges_identical_frames() {
+        let a = frame(0, [255, 0, 0, 255]);
+        let b = frame(16, [255, 0, 0, 255]);
+        let frames = [(a.clone(), 0.0), (a.clone(), 0.1), (b.clone(), 0.2)];
+        let (shown, delays, _) = decode(&encode(&frames, 0.3, GifRepeat::Infinite));
+
+        assert_eq!(delays, [20, 10]);
+        assert_same(&shown[0], &a);
+        assert_same(&shown[1], &b);
+    }
+
+    #[test]
+    fn transparency_after_opaque_frames_shows_through() {
+        let frames = [
+            (frame(0, [255, 0, 0, 255]), 0.0),
+            (frame(8, [255, 0, 0, 255]), 0.1),
+            (frame(16, [0, 0, 0, 0]), 0.2),
+            (frame(24, [0, 255, 0, 255]), 0.3),
+        ];
+        let (shown, delays, _) = decode(&encode(&frames, 0.4, GifRepeat::Infinite));
+
+        assert_eq!(delays, [10, 10, 10, 10]);
+        for (decoded, (expected, _)) in shown.iter().zip(&frames) {
+            assert_same(decoded, expected);
+        }
+    }
+
+    #[test]
+    fn writes_loop_count() {
+        let frames = [(frame(0, [255, 0, 0, 255]), 0.0), (frame(8, [255, 0, 0, 255]), 0.1)];
+        let loops = |repeat| decode(&encode(&frames, 0.2, repeat)).2;
+
+        assert_eq!(loops(GifRepeat::Times(3)), gif::Repeat::Finite(2));
+        // Without the extension decoders play once
+        assert_eq!(loops(GifRepeat::Once), gif::Repeat::Finite(0));
+    }
+}
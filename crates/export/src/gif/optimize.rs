@@ -0,0 +1,189 @@
+//! Optimizer for existing GIF files
+//!
+//! Any GIF is decoded into full frames and re-encoded by the native encoder,
+//! which crops each frame to what changed, leaves unchanged pixels
+//! transparent and merges identical frames. The input is kept when that
+//! doesn't make it smaller.
+
+use super::native::{NativeGifEncoder, MIN_DELAY_CS};
+use super::{GifBackend, GifDither, GifExportConfig, GifQuantizer, GifRepeat};
+use crate::ExportResult;
+use image::codecs::gif::GifDecoder;
+use image::AnimationDecoder;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Delay browsers substitute for ones shorter than [`MIN_DELAY_CS`]
+const BROWSER_DELAY_CS: u64 = 10;
+
+/// Settings for [`optimize_gif`]
+#[derive(Debug, Clone)]
+pub struct GifOptimizeOptions {
+    /// Palette size per frame, 2-256; frames with more colors are
+    /// re-quantized, the others keep their colors exactly
+    pub colors: u16,
+    pub quantizer: GifQuantizer,
+    pub dither: GifDither,
+    /// Dithering strength, 0-100
+    pub dither_strength: u8,
+    /// Lossy LZW level, 0 (lossless) to 100
+    pub lossy: u8,
+    /// Looping of the result; `None` keeps the input's
+    pub repeat: Option<GifRepeat>,
+}
+
+impl Default for GifOptimizeOptions {
+    fn default() -> Self {
+        let defaults = GifExportConfig::default();
+        Self {
+            colors: defaults.colors,
+            quantizer: defaults.quantizer,
+            dither: defaults.dither,
+            dither_strength: defaults.dither_strength,
+            lossy: defaults.lossy,
+            repeat: None,
+        }
+    }
+}
+
+/// Rewrite the GIF at `input` as a smaller GIF at `output`
+///
+/// `output` may be `input` itself; the result is only written once encoding
+/// has finished. Delays shorter than browsers honor are stored as the
+/// delay they actually play with. If the re-encode isn't smaller and the
+/// looping stays the same, `output` gets an unchanged copy of `input`.
+pub fn optimize_gif(
+    input: &Path,
+    output: &Path,
+    options: &GifOptimizeOptions,
+) -> ExportResult<PathBuf> {
+    let original = fs::read(input)?;
+    let input_repeat = read_repeat(&original);
+    let repeat = options.repeat.unwrap_or(input_repeat);
+    let decoder = GifDecoder::new(Cursor::new(original.as_slice()))?;
+    let config = GifExportConfig {
+        output_path: output.to_path_buf(),
+        backend: GifBackend::Native,
+        colors: options.colors,
+        quantizer: options.quantizer,
+        dither: options.dither,
+        dither_strength: options.dither_strength,
+        lossy: options.lossy,
+        repeat,
+        ..Default::default()
+    };
+    let mut encoder = NativeGifEncoder::new(Vec::new(), &config);
+
+    let mut time_cs = 0;
+    for frame in decoder.into_frames() {
+        let frame = frame?;
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let delay_cs = match (numer as f64 / denom.max(1) as f64 / 10.0).round() as u64 {
+            cs if cs < MIN_DELAY_CS => BROWSER_DELAY_CS,
+            cs => cs,
+        };
+        encoder.add_frame(frame.into_buffer(), time_cs as f64 / 100.0)?;
+        time_cs += delay_cs;
+    }
+
+    let data = encoder.finish(time_cs as f64 / 100.0)?;
+    if data.len() >= original.len() && repeat == input_repeat {
+        if output != input {
+            fs::write(output, &original)?;
+        }
+    } else {
+        fs::write(output, data)?;
+    }
+    Ok(output.to_path_buf())
+}
+
+/// Looping stored in the NETSCAPE2.0 (or ANIMEXTS1.0) extension of `gif`;
+/// without one a GIF plays once
+fn read_repeat(gif: &[u8]) -> GifRepeat {
+    // Application extension header, identifier, then the loop sub-block
+    const LOOP_BLOCKS: [&[u8]; 2] = [b"NETSCAPE2.0", b"ANIMEXTS1.0"];
+    let extra = gif.windows(18).find_map(|w| {
+        let is_loop = w[..3] == [0x21, 0xFF, 0x0B]
+            && LOOP_BLOCKS.contains(&&w[3..14])
+            && w[14..16] == [0x03, 0x01];
+        is_loop.then(|| u16::from_le_bytes([w[16], w[17]]))
+    });
+    match extra {
+        None => GifRepeat::Once,
+        // The stored count is plays after the first, 0 looping forever
+        Some(0) => GifRepeat::Infinite,
+        Some(n) => GifRepeat::Times(n.saturating_add(1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::frame;
+
+    /// Full, uncropped frames as a naive encoder writes them
+    fn write_gif(path: &Path, frame_count: usize, repeat: gif::Repeat) {
+        let mut data = Vec::new();
+        let mut encoder = gif::Encoder::new(&mut data, 32, 24, &[]).unwrap();
+        encoder.set_repeat(repeat).unwrap();
+        for i in 0..frame_count {
+            let mut pixels = frame(i).into_raw();
+            let mut gif_frame = gif::Frame::from_rgba_speed(32, 24, &mut pixels, 10);
+            gif_frame.delay = 10;
+            encoder.write_frame(&gif_frame).unwrap();
+        }
+        drop(encoder);
+        fs::write(path, data).unwrap();
+    }
+
+    fn decoded_repeat(path: &Path) -> gif::Repeat {
+        let file = fs::File::open(path).unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(file).unwrap();
+        while decoder.read_next_frame().unwrap().is_some() {}
+        decoder.repeat()
+    }
+
+    #[test]
+    fn keeps_loop_count_and_never_grows() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.gif");
+        let output = dir.path().join("output.gif");
+        write_gif(&input, 8, gif::Repeat::Finite(3));
+
+        optimize_gif(&input, &output, &GifOptimizeOptions::default()).unwrap();
+        assert_eq!(decoded_repeat(&output), gif::Repeat::Finite(3));
+        assert_eq!(read_repeat(&fs::read(&output).unwrap()), GifRepeat::Times(4));
+        assert!(fs::metadata(&output).unwrap().len() <= fs::metadata(&input).unwrap().len());
+    }
+
+    #[test]
+    fn copies_input_when_reencoding_is_not_smaller() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.gif");
+        let optimized = dir.path().join("optimized.gif");
+        let again = dir.path().join("again.gif");
+        write_gif(&input, 4, gif::Repeat::Infinite);
+
+        // A second pass can't improve on the first
+        optimize_gif(&input, &optimized, &GifOptimizeOptions::default()).unwrap();
+        optimize_gif(&optimized, &again, &GifOptimizeOptions::default()).unwrap();
+        assert_eq!(fs::read(&again).unwrap(), fs::read(&optimized).unwrap());
+    }
+
+    #[test]
+    fn reads_loop_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("loop.gif");
+        let cases = [
+            (gif::Repeat::Infinite, GifRepeat::Infinite),
+            (gif::Repeat::Finite(0), GifRepeat::Once),
+            (gif::Repeat::Finite(2), GifRepeat::Times(3)),
+        ];
+        for (stored, expected) in cases {
+            write_gif(&path, 2, stored);
+            assert_eq!(read_repeat(&fs::read(&path).unwrap()), expected);
+        }
+        assert_eq!(read_repeat(b"GIF89a"), GifRepeat::Once);
+    }
+}
@@ -0,0 +1,409 @@
+//! Palette generation for the native GIF encoder
+//!
+//! Frames with few distinct colors keep them exactly; everything else is
+//! reduced by the configured [`GifQuantizer`] over a 5-bit-per-channel
+//! histogram.
+
+use super::GifQuantizer;
+use color_quant::NeuQuant;
+use std::collections::HashMap;
+
+/// Histogram precision per channel
+const BIN_BITS: u32 = 5;
+const BIN_COUNT: usize = 1 << (3 * BIN_BITS);
+
+/// Lloyd iterations when refining with k-means
+const KMEANS_ROUNDS: usize = 8;
+
+/// NeuQuant sampling factor, 1 (best) to 30 (fastest)
+const NEUQUANT_SAMPLING: i32 = 10;
+
+fn bin_of([r, g, b]: [u8; 3]) -> usize {
+    let shift = 8 - BIN_BITS;
+    let (r, g, b) = (r as usize >> shift, g as usize >> shift, b as usize >> shift);
+    (r << (2 * BIN_BITS)) | (g << BIN_BITS) | b
+}
+
+/// Per-channel bin coordinates of `bin`
+fn bin_coords(bin: usize) -> [usize; 3] {
+    let mask = (1 << BIN_BITS) - 1;
+    [bin >> (2 * BIN_BITS), (bin >> BIN_BITS) & mask, bin & mask]
+}
+
+/// Color in the middle of `bin`
+fn bin_center(bin: usize) -> [u8; 3] {
+    let shift = 8 - BIN_BITS;
+    bin_coords(bin).map(|c| ((c << shift) | (1 << (shift - 1))) as u8)
+}
+
+/// Pixel count and channel sums of one histogram bin
+#[derive(Clone, Copy, Default)]
+struct Bin {
+    count: u64,
+    sum: [u64; 3],
+    /// Sum of squared channel values, for Wu's variance
+    sum_sq: u64,
+}
+
+impl Bin {
+    fn add(&mut self, other: &Bin) {
+        self.count += other.count;
+        for c in 0..3 {
+            self.sum[c] += other.sum[c];
+        }
+        self.sum_sq += other.sum_sq;
+    }
+
+    fn mean(&self) -> [u8; 3] {
+        let n = self.count.max(1);
+        self.sum.map(|s| ((s + n / 2) / n) as u8)
+    }
+}
+
+/// Color histogram of a frame
+struct Histogram {
+    bins: Vec<Bin>,
+    /// Indices of the non-empty bins
+    used: Vec<usize>,
+}
+
+impl Histogram {
+    fn new(pixels: &[[u8; 3]]) -> Self {
+        let mut bins = vec![Bin::default(); BIN_COUNT];
+        for &pixel in pixels {
+            let bin = &mut bins[bin_of(pixel)];
+            bin.count += 1;
+            for (sum, value) in bin.sum.iter_mut().zip(pixel) {
+                *sum += value as u64;
+                bin.sum_sq += value as u64 * value as u64;
+            }
+        }
+        let used = (0..BIN_COUNT).filter(|&i| bins[i].count > 0).collect();
+        Self { bins, used }
+    }
+}
+
+/// Colors of a frame mapped to palette indices
+pub(crate) struct ColorMap {
+    pub(crate) palette: Vec<[u8; 3]>,
+    lookup: Lookup,
+}
+
+enum Lookup {
+    Exact(HashMap<[u8; 3], u8>),
+    /// Palette index per histogram bin, filled in on first use for bins
+    /// the frame itself didn't hit
+    Binned(Vec<Option<u8>>),
+}
+
+impl ColorMap {
+    /// Palette of at most `max_colors` (1-256) for `pixels`
+    pub(crate) fn new(pixels: &[[u8; 3]], max_colors: usize, quantizer: GifQuantizer) -> Self {
+        let max_colors = max_colors.clamp(1, 256);
+
+        let mut exact = HashMap::new();
+        for &pixel in pixels {
+            let next = exact.len();
+            exact.entry(pixel).or_insert(next as u8);
+            if exact.len() > max_colors {
+                return Self::reduced(pixels, max_colors, quantizer);
+            }
+        }
+
+        let mut palette = vec![[0; 3]; exact.len()];
+        for (&color, &index) in &exact {
+            palette[index as usize] = color;
+        }
+        Self { palette, lookup: Lookup::Exact(exact) }
+    }
+
+    fn reduced(pixels: &[[u8; 3]], max_colors: usize, quantizer: GifQuantizer) -> Self {
+        let histogram = Histogram::new(pixels);
+        let palette = match quantizer {
+            GifQuantizer::MedianCut => median_cut(&histogram, max_colors),
+            GifQuantizer::Wu => wu(&histogram, max_colors),
+            GifQuantizer::NeuQuant => neuquant(pixels, max_colors),
+            GifQuantizer::KMeans => kmeans(&histogram, median_cut(&histogram, max_colors)),
+        };
+
+        let mut lookup = vec![None; BIN_COUNT];
+        for &bin in &histogram.used {
+            lookup[bin] = nearest(&palette, histogram.bins[bin].mean());
+        }
+        Self { palette, lookup: Lookup::Binned(lookup) }
+    }
+
+    /// Whether every color of the frame is in the palette as it is
+    pub(crate) fn is_exact(&self) -> bool {
+        matches!(self.lookup, Lookup::Exact(_))
+    }
+
+    /// Palette index for `pixel`
+    ///
+    /// Exact maps only know the colors passed to [`ColorMap::new`]; reduced
+    /// ones map any color.
+    pub(crate) fn index_of(&mut self, pixel: [u8; 3]) -> u8 {
+        match &mut self.lookup {
+            Lookup::Exact(map) => map.get(&pixel).copied().unwrap_or(0),
+            Lookup::Binned(table) => {
+                let bin = bin_of(pixel);
+                *table[bin].get_or_insert_with(|| {
+                    nearest(&self.palette, bin_center(bin)).unwrap_or(0)
+                })
+            }
+        }
+    }
+}
+
+/// Index of the palette entry closest to `color`
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> Option<u8> {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, p)| {
+            (0..3).map(|c| (p[c] as i32 - color[c] as i32).pow(2)).sum::<i32>()
+        })
+        .map(|(i, _)| i as u8)
+}
+
+fn median_cut(histogram: &Histogram, max_colors: usize) -> Vec<[u8; 3]> {
+    let bins = &histogram.bins;
+    let mut boxes = vec![ColorBox::new(histogram.used.clone(), bins)];
+    while boxes.len() < max_colors {
+        // Split the box spanning the most pixels times its longest side
+        let Some((i, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.bins.len() > 1)
+            .max_by_key(|(_, b)| b.count * b.longest_side().1 as u64)
+        else {
+            break;
+        };
+        let (a, b) = boxes.swap_remove(i).split(bins);
+        boxes.push(a);
+        boxes.push(b);
+    }
+
+    boxes.iter().map(|b| b.mean(bins)).collect()
+}
+
+/// Move each entry of `palette` to the mean of the colors nearest to it
+/// until it settles
+fn kmeans(histogram: &Histogram, mut palette: Vec<[u8; 3]>) -> Vec<[u8; 3]> {
+    for _ in 0..KMEANS_ROUNDS {
+        let mut clusters = vec![Bin::default(); palette.len()];
+        for &bin in &histogram.used {
+            let bin = &histogram.bins[bin];
+            if let Some(i) = nearest(&palette, bin.mean()) {
+                clusters[i as usize].add(bin);
+            }
+        }
+
+        let next: Vec<[u8; 3]> = clusters
+            .iter()
+            .zip(&palette)
+            .map(|(cluster, &old)| if cluster.count > 0 { cluster.mean() } else { old })
+            .collect();
+        if next == palette {
+            break;
+        }
+        palette = next;
+    }
+    palette
+}
+
+fn neuquant(pixels: &[[u8; 3]], max_colors: usize) -> Vec<[u8; 3]> {
+    let rgba: Vec<u8> = pixels.iter().flat_map(|&[r, g, b]| [r, g, b, 255]).collect();
+    NeuQuant::new(NEUQUANT_SAMPLING, max_colors, &rgba)
+        .color_map_rgb()
+        .chunks_exact(3)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect()
+}
+
+/// A set of histogram bins
+struct ColorBox {
+    bins: Vec<usize>,
+    count: u64,
+    min: [u8; 3],
+    max: [u8; 3],
+}
+
+impl ColorBox {
+    fn new(bins: Vec<usize>, histogram: &[Bin]) -> Self {
+        let mut min = [u8::MAX; 3];
+        let mut max = [0; 3];
+        let mut count = 0;
+        for &bin in &bins {
+            let mean = histogram[bin].mean();
+            count += histogram[bin].count;
+            for c in 0..3 {
+                min[c] = min[c].min(mean[c]);
+                max[c] = max[c].max(mean[c]);
+            }
+        }
+        Self { bins, count, min, max }
+    }
+
+    /// Channel with the widest range, and that range
+    fn longest_side(&self) -> (usize, u8) {
+        (0..3)
+            .map(|c| (c, self.max[c] - self.min[c]))
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    }
+
+    /// Split at the pixel-weighted median of the longest side
+    fn split(mut self, histogram: &[Bin]) -> (Self, Self) {
+        let (channel, _) = self.longest_side();
+        self.bins.sort_by_key(|&bin| histogram[bin].mean()[channel]);
+
+        let half = self.count / 2;
+        let mut seen = 0;
+        let mut at = 1;
+        for (i, &bin) in self.bins.iter().enumerate() {
+            seen += histogram[bin].count;
+            if seen >= half {
+                at = i + 1;
+                break;
+            }
+        }
+        let at = at.clamp(1, self.bins.len() - 1);
+
+        let upper = self.bins.split_off(at);
+        (Self::new(self.bins, histogram), Self::new(upper, histogram))
+    }
+
+    fn mean(&self, histogram: &[Bin]) -> [u8; 3] {
+        let mut total = Bin::default();
+        for &bin in &self.bins {
+            total.add(&histogram[bin]);
+        }
+        total.mean()
+    }
+}
+
+/// Grid side of Wu's cumulative moments, one more than the bins per channel
+/// so index 0 can stand for "nothing below"
+const WU_SIDE: usize = (1 << BIN_BITS) + 1;
+
+/// Box of the moment grid, exclusive below and inclusive above
+#[derive(Clone, Copy)]
+struct WuBox {
+    lo: [usize; 3],
+    hi: [usize; 3],
+}
+
+/// Count, channel sums and sum of squares, summed over a region
+type Moment = [f64; 5];
+
+/// Wu's quantizer: repeatedly cut the box with the largest variance where it
+/// reduces the variance the most, using cumulative moments
+fn wu(histogram: &Histogram, max_colors: usize) -> Vec<[u8; 3]> {
+    let at = |p: [usize; 3]| (p[0] * WU_SIDE + p[1]) * WU_SIDE + p[2];
+
+    let mut moments = vec![[0.0; 5]; WU_SIDE * WU_SIDE * WU_SIDE];
+    for &bin in &histogram.used {
+        let b = &histogram.bins[bin];
+        let [r, g, bl] = bin_coords(bin);
+        moments[at([r + 1, g + 1, bl + 1])] = [
+            b.count as f64,
+            b.sum[0] as f64,
+            b.sum[1] as f64,
+            b.sum[2] as f64,
+            b.sum_sq as f64,
+        ];
+    }
+    // Prefix sums along each axis in turn
+    for axis in 0..3 {
+        for i in 0..moments.len() {
+            let mut p = [i / (WU_SIDE * WU_SIDE), i / WU_SIDE % WU_SIDE, i % WU_SIDE];
+            if p[axis] == 0 {
+                continue;
+            }
+            p[axis] -= 1;
+            let below = moments[at(p)];
+            for (m, b) in moments[i].iter_mut().zip(below) {
+                *m += b;
+            }
+        }
+    }
+
+    let volume = |b: &WuBox| -> Moment {
+        let mut total = [0.0; 5];
+        for corner in 0..8 {
+            let p: [usize; 3] =
+                std::array::from_fn(|axis| if corner >> axis & 1 == 1 { b.lo[axis] } else { b.hi[axis] });
+            let negative = (corner as u32).count_ones() % 2 == 1;
+            for (t, m) in total.iter_mut().zip(moments[at(p)]) {
+                *t += if negative { -m } else { m };
+            }
+        }
+        total
+    };
+    // Sum of squares that would be explained by the box's mean
+    let explained = |m: &Moment| (m[1] * m[1] + m[2] * m[2] + m[3] * m[3]) / m[0];
+    let variance = |b: &WuBox| {
+        let m = volume(b);
+        if m[0] > 0.0 {
+            m[4] - explained(&m)
+        } else {
+            0.0
+        }
+    };
+    let cut = |b: &WuBox| -> Option<(WuBox, WuBox)> {
+        let whole = volume(b);
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            for pos in b.lo[axis] + 1..b.hi[axis] {
+                let mut half = *b;
+                half.hi[axis] = pos;
+                let lower = volume(&half);
+                let upper: Moment = std::array::from_fn(|k| whole[k] - lower[k]);
+                if lower[0] <= 0.0 || upper[0] <= 0.0 {
+                    continue;
+                }
+                let score = explained(&lower) + explained(&upper);
+                if best.is_none_or(|(s, _, _)| score > s) {
+                    best = Some((score, axis, pos));
+                }
+            }
+        }
+        best.map(|(_, axis, pos)| {
+            let (mut lower, mut upper) = (*b, *b);
+            lower.hi[axis] = pos;
+            upper.lo[axis] = pos;
+            (lower, upper)
+        })
+    };
+
+    let mut boxes = vec![WuBox { lo: [0; 3], hi: [WU_SIDE - 1; 3] }];
+    let mut variances = vec![variance(&boxes[0])];
+    while boxes.len() < max_colors {
+        let Some((i, _)) = variances
+            .iter()
+            .enumerate()
+            .filter(|(_, &v)| v > 0.0)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+        else {
+            break;
+        };
+        match cut(&boxes[i]) {
+            Some((lower, upper)) => {
+                boxes[i] = lower;
+                variances[i] = variance(&lower);
+                boxes.push(upper);
+                variances.push(variance(&upper));
+            }
+            // A single bin, nothing left to cut
+            None => variances[i] = 0.0,
+        }
+    }
+
+    boxes
+        .iter()
+        .map(volume)
+        .filter(|m| m[0] > 0.0)
+        .map(|m| [1, 2, 3].map(|c| (m[c] / m[0]).round() as u8))
+        .collect()
+}
@@ -0,0 +1,263 @@
+//! Export under a maximum file size
+//!
+//! Compression is raised first, then the animation is scaled down and
+//! frames are thinned out until the file fits.
+
+use super::{GifExportConfig, GifExporter};
+use crate::registry::{ExportOptions, OptionValue};
+use crate::util::fit_dimensions;
+use crate::{ExportError, ExportResult, ProgressCallback};
+use std::fs;
+use std::path::PathBuf;
+
+/// Compression steps as (gifski quality cap, native lossy floor)
+const COMPRESSION_LEVELS: [(u8, u8); 4] = [(100, 0), (80, 20), (65, 40), (50, 60)];
+
+/// Output scale and frame step tried at the strongest compression, least
+/// degrading first
+const REDUCTIONS: [(f64, usize); 10] = [
+    (0.85, 1),
+    (0.7, 1),
+    (0.6, 1),
+    (0.6, 2),
+    (0.5, 2),
+    (0.4, 2),
+    (0.4, 3),
+    (0.33, 3),
+    (0.25, 3),
+    (0.25, 4),
+];
+
+/// Outcome of [`GifExporter::export_to_size`]
+#[derive(Debug, Clone)]
+pub struct GifSizeFit {
+    pub path: PathBuf,
+    /// Size of the written file in bytes
+    pub size: u64,
+    /// Settings of the encode that was kept
+    pub config: GifExportConfig,
+    /// Every `frame_step`th frame was kept, each shown in place of the
+    /// dropped ones after it
+    pub frame_step: usize,
+    /// Whether `size` is within the requested maximum
+    pub fits: bool,
+}
+
+impl GifSizeFit {
+    /// `base` with the compression and size this fit chose, plus its
+    /// `frame_step`
+    pub(crate) fn options(&self, base: &ExportOptions) -> ExportOptions {
+        let mut options = base.clone();
+        options.set("quality", OptionValue::Int(self.config.quality as i64));
+        options.set("lossy", OptionValue::Int(self.config.lossy as i64));
+        for (key, value) in [("width", self.config.width), ("height", self.config.height)] {
+            if let Some(value) = value {
+                options.set(key, OptionValue::Int(value as i64));
+            }
+        }
+        options.set("frame_step", OptionValue::Int(self.frame_step as i64));
+        options
+    }
+}
+
+impl GifExporter {
+    /// Export PNG files to a GIF of at most `max_bytes`
+    ///
+    /// Starts from `config` and raises compression, then lowers the size
+    /// and frame rate, skipping steps the previous attempt predicts won't
+    /// fit. Each attempt advances progress by an equal share of what is
+    /// left for the attempts that may still follow, so it never goes back.
+    /// When even the smallest step is too large, that attempt is kept with
+    /// `fits: false`.
+    pub fn export_to_size(
+        png_paths: &[PathBuf],
+        config: GifExportConfig,
+        max_bytes: u64,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<GifSizeFit> {
+        let Some(first) = png_paths.first() else {
+            return Err(ExportError::NoFrames);
+        };
+        let (width, height) = image::image_dimensions(first)?;
+        let (base_width, base_height) = fit_dimensions(width, height, config.width, config.height);
+        let mut done = 0.0;
+        let mut try_config = |config: GifExportConfig,
+                              frame_step,
+                              attempts_left: usize|
+         -> ExportResult<GifSizeFit> {
+            let (start, share) = (done, (1.0 - done) / attempts_left as f32);
+            let report = |p: f32| {
+                if let Some(cb) = &progress {
+                    cb(start + share * p);
+                }
+            };
+            let size = Self::encode_attempt(png_paths, &config, frame_step, &report)?;
+            done += share;
+            Ok(GifSizeFit {
+                path: config.output_path.clone(),
+                size,
+                config,
+                frame_step,
+                fits: size <= max_bytes,
+            })
+        };
+
+        let mut attempt = None;
+        let mut level = 0;
+        while level < COMPRESSION_LEVELS.len() {
+            let (quality_cap, lossy_floor) = COMPRESSION_LEVELS[level];
+            let fit = try_config(
+                GifExportConfig {
+                    quality: config.quality.min(quality_cap),
+                    lossy: config.lossy.max(lossy_floor),
+                    ..config.clone()
+                },
+                1,
+                COMPRESSION_LEVELS.len() - level + REDUCTIONS.len(),
+            )?;
+            if fit.fits {
+                return Ok(fit);
+            }
+            // Compression alone won't halve the size, go straight to the strongest
+            level = if level == 0 && fit.size > max_bytes * 2 {
+                COMPRESSION_LEVELS.len() - 1
+            } else {
+                level + 1
+            };
+            attempt = Some(fit);
+        }
+        let compressed = attempt.ok_or(ExportError::NoFrames)?;
+
+        // Size grows roughly with the pixel count and the number of frames
+        let estimate = |scale: f64, step: usize| compressed.size as f64 * scale * scale / step as f64;
+        let mut correction = 1.0;
+        let mut last = None;
+        for (i, &(scale, frame_step)) in REDUCTIONS.iter().enumerate() {
+            let is_last = i + 1 == REDUCTIONS.len();
+            if estimate(scale, frame_step) * correction > max_bytes as f64 && !is_last {
+                continue;
+            }
+
+            let fit = try_config(
+                GifExportConfig {
+                    width: Some(((base_width as f64 * scale).round() as u32).max(1)),
+                    height: Some(((base_height as f64 * scale).round() as u32).max(1)),
+                    ..compressed.config.clone()
+                },
+                frame_step,
+                REDUCTIONS.len() - i,
+            )?;
+            if fit.fits {
+                return Ok(fit);
+            }
+            correction = fit.size as f64 / estimate(scale, frame_step);
+            last = Some(fit);
+        }
+        Ok(last.unwrap_or(compressed))
+    }
+
+    /// Encode every `frame_step`th frame, repeated over the dropped ones so
+    /// timing is unchanged, and return the file size
+    fn encode_attempt(
+        png_paths: &[PathBuf],
+        config: &GifExportConfig,
+        frame_step: usize,
+        progress: &dyn Fn(f32),
+    ) -> ExportResult<u64> {
+        let total = png_paths.len();
+        let mut exporter = Self::new(config.clone())?;
+        exporter.start()?;
+
+        let mut kept = None;
+        for (i, path) in png_paths.iter().enumerate() {
+            if i % frame_step.max(1) == 0 {
+                kept = Some(image::open(path)?.to_rgba8());
+            }
+            if let Some(image) = &kept {
+                exporter.add_frame(image.clone())?;
+            }
+
+            progress((i + 1) as f32 / total as f32);
+        }
+
+        exporter.finish()?;
+        Ok(fs::metadata(&config.output_path)?.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::sidecar_path;
+    use crate::registry::{ExportConfig, ExportOptions, ExportRegistry, OptionValue};
+    use crate::ExportError;
+    use image::{Rgba, RgbaImage};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+
+    /// Noise frames, which compress poorly and need the later fit steps
+    fn noise_frames(dir: &Path, count: usize) -> Vec<PathBuf> {
+        let mut state = 0x2545_F491u32;
+        (0..count)
+            .map(|i| {
+                let image = RgbaImage::from_fn(80, 60, |_, _| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    let [r, g, b, _] = state.to_be_bytes();
+                    Rgba([r, g, b, 255])
+                });
+                let path = dir.join(format!("frame_{:05}.png", i));
+                image.save(&path).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    fn config(output: PathBuf, max_size_kb: i64) -> ExportConfig {
+        let mut options = ExportOptions::new();
+        options.set("max_size_kb", OptionValue::Int(max_size_kb));
+        ExportConfig { output_path: output, options, sidecar: true, ..Default::default() }
+    }
+
+    #[test]
+    fn fits_under_limit_and_records_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = noise_frames(dir.path(), 6);
+        let gif = ExportRegistry::default().get("gif").unwrap();
+
+        let unconstrained = dir.path().join("full.gif");
+        gif.export(&frames, &config(unconstrained.clone(), 0), None).unwrap();
+        let limit_kb = std::fs::metadata(&unconstrained).unwrap().len() / 1024 / 3;
+
+        let output = dir.path().join("fitted.gif");
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let sink = reported.clone();
+        let progress = Box::new(move |p: f32| sink.lock().unwrap().push(p));
+        gif.export(&frames, &config(output.clone(), limit_kb as i64), Some(progress)).unwrap();
+        assert!(std::fs::metadata(&output).unwrap().len() <= limit_kb * 1024);
+
+        let reported = reported.lock().unwrap();
+        assert!(reported.len() > frames.len(), "expected several attempts");
+        assert!(reported.windows(2).all(|w| w[0] <= w[1]), "progress went backwards");
+        assert!(reported.iter().all(|p| (0.0..=1.0).contains(p)));
+
+        let json = std::fs::read(sidecar_path(&output)).unwrap();
+        let sidecar: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let options = &sidecar["export"]["options"];
+        assert_eq!(options["max_size_kb"], limit_kb);
+        assert!(options["lossy"].as_i64().unwrap() > 0);
+        assert!(options["frame_step"].as_i64().unwrap() >= 1);
+        assert!(options["width"].as_i64().is_none_or(|w| w < 80));
+    }
+
+    #[test]
+    fn removes_output_that_cannot_fit() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = noise_frames(dir.path(), 6);
+        let output = dir.path().join("tiny.gif");
+
+        let gif = ExportRegistry::default().get("gif").unwrap();
+        let result = gif.export(&frames, &config(output.clone(), 1), None);
+        assert!(matches!(result, Err(ExportError::GifEncode(_))));
+        assert!(!output.exists());
+        assert!(!sidecar_path(&output).exists());
+    }
+}
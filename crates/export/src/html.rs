@@ -0,0 +1,344 @@
+//! Self-contained HTML player export
+//!
+//! Every frame is embedded as a data URI, followed by a small script that
+//! plays them on a canvas with pause, scrubbing, speed control and stepping.
+
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, frame_time, resize_to};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use image::RgbaImage;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Image format used for the embedded frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtmlFrameFormat {
+    /// Lossless, largest files
+    Png,
+    /// Lossy WebP at the configured quality
+    WebP,
+}
+
+/// HTML player export configuration
+#[derive(Debug, Clone)]
+pub struct HtmlExportConfig {
+    pub output_path: PathBuf,
+    pub fps: u8,
+    /// Capture time of each frame, relative to the first; frames past its
+    /// end, or all frames when empty, follow `fps`
+    pub timestamps: Vec<Duration>,
+    pub frame_format: HtmlFrameFormat,
+    /// WebP quality, 0-100
+    pub quality: u8,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Default for HtmlExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            timestamps: Vec::new(),
+            frame_format: HtmlFrameFormat::WebP,
+            quality: 90,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+/// HTML player exporter
+pub struct HtmlExporter {
+    config: HtmlExportConfig,
+    writer: Option<BufWriter<File>>,
+    size: Option<(u32, u32)>,
+    frame_count: usize,
+    progress: Option<(ProgressCallback, usize)>,
+}
+
+impl HtmlExporter {
+    /// Create a new HTML exporter
+    pub fn new(config: HtmlExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            writer: None,
+            size: None,
+            frame_count: 0,
+            progress: None,
+        })
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        let title = self
+            .config
+            .output_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "WinGIF".to_string());
+
+        let mut writer = BufWriter::new(File::create(&self.config.output_path)?);
+        write!(writer, "{}", HTML_HEAD.replace("{title}", &escape_html(&title)))?;
+        writeln!(writer, "const FRAMES = [")?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    /// Add a frame to the player
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        let writer = self.writer.as_mut()
+            .ok_or_else(|| ExportError::HtmlEncode("Exporter not started".to_string()))?;
+
+        let (width, height) = *self.size.get_or_insert_with(|| {
+            fit_dimensions(image.width(), image.height(), self.config.width, self.config.height)
+        });
+        let image = resize_to(image, width, height);
+
+        let (mime, data) = match self.config.frame_format {
+            HtmlFrameFormat::Png => {
+                let mut png = Cursor::new(Vec::new());
+                image.write_to(&mut png, image::ImageFormat::Png)?;
+                ("image/png", png.into_inner())
+            }
+            HtmlFrameFormat::WebP => {
+                let encoder = webp::Encoder::from_rgba(image.as_raw(), width, height);
+                let quality = self.config.quality.min(100) as f32;
+                ("image/webp", encoder.encode(quality).to_vec())
+            }
+        };
+        writeln!(writer, "\"data:{};base64,{}\",", mime, BASE64.encode(data))?;
+
+        self.frame_count += 1;
+        if let Some((ref cb, total)) = self.progress {
+            cb(self.frame_count as f32 / total.max(1) as f32);
+        }
+        Ok(())
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        let mut writer = self.writer.take()
+            .ok_or_else(|| ExportError::HtmlEncode("Exporter not started".to_string()))?;
+        if self.frame_count == 0 {
+            return Err(ExportError::NoFrames);
+        }
+
+        // Cumulative rounding keeps the total duration exact
+        let millis = |index| {
+            (frame_time(&self.config.timestamps, self.config.fps, index) * 1000.0).round() as u64
+        };
+        let delays: Vec<String> = (0..self.frame_count)
+            .map(|i| millis(i + 1).saturating_sub(millis(i)).to_string())
+            .collect();
+
+        writeln!(writer, "];")?;
+        writeln!(writer, "const DELAYS = [{}];", delays.join(","))?;
+        write!(writer, "{}", PLAYER_SCRIPT)?;
+        writer.flush()?;
+
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to an HTML player
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: HtmlExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let mut exporter = Self::new(config)?;
+        exporter.progress = progress.map(|cb| (cb, png_paths.len()));
+        exporter.start()?;
+
+        for path in png_paths {
+            let img = image::open(path)?.to_rgba8();
+            exporter.add_frame(img)?;
+        }
+
+        exporter.finish()
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+  body { margin: 0; background: #1e1e1e; color: #ddd; font: 13px sans-serif; }
+  #stage { display: flex; justify-content: center; padding: 16px; }
+  canvas { max-width: 100%; background: repeating-conic-gradient(#444 0 25%, #333 0 50%) 0 0 / 16px 16px; }
+  #controls { display: flex; gap: 8px; align-items: center; padding: 8px 16px; }
+  #seek { flex: 1; }
+  button, select { background: #333; color: #ddd; border: 1px solid #555; padding: 4px 10px; }
+  #info { font-variant-numeric: tabular-nums; min-width: 160px; text-align: right; }
+</style>
+</head>
+<body>
+<div id="stage"><canvas id="view"></canvas></div>
+<div id="controls">
+  <button id="prev" title="Previous frame (Left)">&#9664;&#9646;</button>
+  <button id="play" title="Play/Pause (Space)">Pause</button>
+  <button id="next" title="Next frame (Right)">&#9646;&#9654;</button>
+  <input id="seek" type="range" min="0" value="0">
+  <select id="speed" title="Playback speed">
+    <option value="0.25">0.25x</option>
+    <option value="0.5">0.5x</option>
+    <option value="1" selected>1x</option>
+    <option value="2">2x</option>
+    <option value="4">4x</option>
+  </select>
+  <span id="info"></span>
+</div>
+<script>
+"#;
+
+const PLAYER_SCRIPT: &str = r#"(function () {
+  const canvas = document.getElementById("view");
+  const ctx = canvas.getContext("2d");
+  const seek = document.getElementById("seek");
+  const play = document.getElementById("play");
+  const speed = document.getElementById("speed");
+  const info = document.getElementById("info");
+  const starts = [];
+  let total = 0;
+  for (const d of DELAYS) { starts.push(total); total += d; }
+
+  let index = 0, playing = true, elapsed = 0, last = null;
+  const images = FRAMES.map(src => { const img = new Image(); img.src = src; return img; });
+  seek.max = FRAMES.length - 1;
+
+  function show(i) {
+    index = (i + FRAMES.length) % FRAMES.length;
+    const img = images[index];
+    if (img.complete) {
+      canvas.width = img.naturalWidth;
+      canvas.height = img.naturalHeight;
+      ctx.drawImage(img, 0, 0);
+    } else {
+      img.onload = () => { if (index === images.indexOf(img)) show(index); };
+    }
+    seek.value = index;
+    info.textContent = (index + 1) + " / " + FRAMES.length + "  " + (starts[index] / 1000).toFixed(2) + "s";
+  }
+
+  function setPlaying(p) {
+    playing = p;
+    play.textContent = p ? "Pause" : "Play";
+    elapsed = 0;
+    last = null;
+  }
+
+  function tick(now) {
+    if (playing) {
+      if (last !== null) elapsed += (now - last) * parseFloat(speed.value);
+      last = now;
+      let i = index;
+      while (total > 0 && elapsed >= DELAYS[i]) { elapsed -= DELAYS[i]; i = (i + 1) % FRAMES.length; }
+      if (i !== index) show(i);
+    }
+    requestAnimationFrame(tick);
+  }
+
+  function step(delta) { setPlaying(false); show(index + delta); }
+
+  play.onclick = () => setPlaying(!playing);
+  document.getElementById("prev").onclick = () => step(-1);
+  document.getElementById("next").onclick = () => step(1);
+  seek.oninput = () => { setPlaying(false); show(parseInt(seek.value, 10)); };
+  document.addEventListener("keydown", e => {
+    if (e.target === speed) return;
+    switch (e.key) {
+      case " ": setPlaying(!playing); break;
+      case "ArrowLeft": step(-1); break;
+      case "ArrowRight": step(1); break;
+      case "Home": step(-index); break;
+      case "End": step(FRAMES.length - 1 - index); break;
+      default: return;
+    }
+    e.preventDefault();
+  });
+
+  show(0);
+  requestAnimationFrame(tick);
+})();
+</script>
+</body>
+</html>
+"#;
+
+/// HTML player entry in the format registry
+pub(crate) struct HtmlFormat;
+
+impl Exporter for HtmlFormat {
+    fn id(&self) -> &'static str {
+        "html"
+    }
+
+    fn name(&self) -> &'static str {
+        "HTML player"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["html", "htm"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            alpha: true,
+            variable_delays: true,
+            max_colors: None,
+            lossy: true,
+            lossless: true,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = HtmlExportConfig::default();
+        let mut options = vec![
+            OptionSpec::choice("frame_format", "Frame format", &["webp", "png"], "webp"),
+            OptionSpec::int("quality", "WebP quality", 0, 100, defaults.quality as i64),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let defaults = HtmlExportConfig::default();
+        let options = &config.options;
+        let html_config = HtmlExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            timestamps: config.frame_timestamps(png_paths.len()),
+            frame_format: match options.choice_or("frame_format", "webp") {
+                "png" => HtmlFrameFormat::Png,
+                _ => HtmlFrameFormat::WebP,
+            },
+            quality: options.u8_or("quality", defaults.quality),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+        };
+        HtmlExporter::export_from_pngs(png_paths, html_config, progress)
+    }
+}
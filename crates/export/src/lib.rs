@@ -1,14 +1,55 @@
 //! Export module for WinGIF
 //!
-//! Provides GIF and PNG export functionality.
+//! Provides GIF, APNG, WebP, AVIF, AV1/H.264 video, Y4M, sprite sheet,
+//! HTML player, storyboard, still image and PNG export functionality.
 
+mod apng;
+mod cancel;
 mod gif;
+mod html;
+mod metadata;
+mod multi;
 mod png;
+mod preset;
+mod registry;
+mod sprite;
+mod still;
+mod storyboard;
+#[cfg(test)]
+mod test_util;
+mod util;
+mod video;
+mod webp;
+mod y4m;
+mod yuv;
 
-pub use gif::{GifExporter, GifExportConfig};
-pub use png::PngExporter;
+pub use apng::{ApngExportConfig, ApngExporter};
+pub use cancel::CancelToken;
+pub use gif::{
+    optimize_gif, GifBackend, GifDither, GifExporter, GifExportConfig, GifOptimizeOptions,
+    GifPlayback, GifQuantizer, GifRepeat, GifSizeFit,
+};
+pub use html::{HtmlExportConfig, HtmlExporter, HtmlFrameFormat};
+pub use metadata::{
+    sidecar_path, CaptureInfo, CaptureRegion, CaptureTargetKind, EmbeddedMetadata,
+};
+pub use multi::{export_multi, ExportTarget, MultiExportOptions};
+pub use png::{PngExporter, PngSequenceConfig, SequenceFormat};
+pub use preset::{ExportPreset, PresetWarning, PRESETS};
+pub use registry::{
+    ExportConfig, ExportOptions, ExportRegistry, Exporter, FormatCapabilities, OptionKind,
+    OptionSpec, OptionValue, DEFAULT_FORMATS,
+};
+pub use sprite::{SpriteSheetExportConfig, SpriteSheetExporter};
+pub use still::{StillExportConfig, StillExporter, StillImageFormat};
+pub use storyboard::{StoryboardExportConfig, StoryboardExporter, StoryboardSelection};
+pub use video::{
+    Av1ExportConfig, Av1Exporter, AvifExportConfig, AvifExporter, H264ExportConfig, H264Exporter,
+    VideoContainer, VideoPreset,
+};
+pub use webp::{WebPExportConfig, WebPExporter};
+pub use y4m::{Y4mChroma, Y4mExportConfig, Y4mExporter};
 
-use std::path::PathBuf;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -22,6 +63,36 @@ pub enum ExportError {
     #[error("GIF encoding error: {0}")]
     GifEncode(String),
 
+    #[error("APNG encoding error: {0}")]
+    ApngEncode(String),
+
+    #[error("WebP encoding error: {0}")]
+    WebPEncode(String),
+
+    #[error("Video encoding error: {0}")]
+    VideoEncode(String),
+
+    #[error("Y4M error: {0}")]
+    Y4mEncode(String),
+
+    #[error("Storyboard error: {0}")]
+    StoryboardEncode(String),
+
+    #[error("HTML export error: {0}")]
+    HtmlEncode(String),
+
+    #[error("Sprite sheet error: {0}")]
+    SpriteEncode(String),
+
+    #[error("Metadata error: {0}")]
+    MetadataEncode(String),
+
+    #[error("Still image error: {0}")]
+    StillEncode(String),
+
+    #[error("ZIP archive error: {0}")]
+    ZipEncode(String),
+
     #[error("No frames to export")]
     NoFrames,
 
@@ -33,30 +104,3 @@ pub type ExportResult<T> = Result<T, ExportError>;
 
 /// Progress callback type
 pub type ProgressCallback = Box<dyn Fn(f32) + Send>;
-
-/// Export format
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ExportFormat {
-    Gif,
-    PngSequence,
-}
-
-/// Common export configuration
-#[derive(Debug, Clone)]
-pub struct ExportConfig {
-    pub format: ExportFormat,
-    pub output_path: PathBuf,
-    pub fps: u8,
-    pub quality: u8,
-}
-
-impl Default for ExportConfig {
-    fn default() -> Self {
-        Self {
-            format: ExportFormat::Gif,
-            output_path: PathBuf::new(),
-            fps: 15,
-            quality: 90,
-        }
-    }
-}
@@ -0,0 +1,573 @@
+//! Metadata sidecar and embedded metadata
+//!
+//! Records what was captured and how it was encoded in a `<output>.json`
+//! file next to the export, and builds the descriptive fields that formats
+//! store inside the file: GIF comment and XMP application extensions, PNG
+//! text chunks and XMP packets for WebP and AVIF.
+
+use crate::registry::{ExportConfig, ExportOptions};
+use crate::{ExportError, ExportResult};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Kind of capture target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureTargetKind {
+    Monitor,
+    Window,
+}
+
+/// Captured screen region in physical pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CaptureRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Details of the recording an export was made from
+#[derive(Debug, Clone)]
+pub struct CaptureInfo {
+    pub target: CaptureTargetKind,
+    pub region: CaptureRegion,
+    /// Frame rate the recording was started with
+    pub requested_fps: u8,
+    /// Wall-clock length of the recording
+    pub duration: Duration,
+    /// Capture time of each exported frame, relative to the first
+    pub frame_timestamps: Vec<Duration>,
+}
+
+/// Descriptive fields written into the exported file itself
+///
+/// Unset fields are left out; when all are unset nothing is embedded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmbeddedMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Title of the captured window
+    pub source_window: Option<String>,
+    /// When the recording was made
+    pub capture_date: Option<SystemTime>,
+    pub description: Option<String>,
+}
+
+impl EmbeddedMetadata {
+    /// Whether there is nothing to embed
+    pub fn is_empty(&self) -> bool {
+        self.fields().is_empty()
+    }
+
+    /// Set fields as (PNG text keyword, value), plus the writing software
+    /// when any field is set
+    pub(crate) fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields: Vec<(&'static str, String)> = [
+            ("Title", self.title.clone()),
+            ("Author", self.author.clone()),
+            ("Source Window", self.source_window.clone()),
+            ("Creation Time", self.capture_date.map(rfc3339)),
+            ("Description", self.description.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value.filter(|v| !v.is_empty())?)))
+        .collect();
+        if !fields.is_empty() {
+            fields.push(("Software", software()));
+        }
+        fields
+    }
+
+    /// One `Key: value` line per field, for plain text comments
+    pub(crate) fn comment(&self) -> String {
+        self.fields()
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// XMP packet with the fields as Dublin Core and XMP basic properties
+    pub(crate) fn xmp(&self) -> String {
+        let mut properties = String::new();
+        let mut add = |name: &str, value: String| {
+            properties.push_str(&format!("   <{0}>{1}</{0}>\n", name, value));
+        };
+        let alt = |text: &str| {
+            format!(
+                "<rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt>",
+                xml_escape(text)
+            )
+        };
+        let non_empty = |field: &Option<String>| field.clone().filter(|v| !v.is_empty());
+
+        if let Some(title) = non_empty(&self.title) {
+            add("dc:title", alt(&title));
+        }
+        if let Some(author) = non_empty(&self.author) {
+            add("dc:creator", format!("<rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq>", xml_escape(&author)));
+        }
+        if let Some(description) = non_empty(&self.description) {
+            add("dc:description", alt(&description));
+        }
+        if let Some(window) = non_empty(&self.source_window) {
+            add("dc:source", xml_escape(&window));
+        }
+        if let Some(date) = self.capture_date {
+            add("xmp:CreateDate", rfc3339(date));
+        }
+        add("xmp:CreatorTool", xml_escape(&software()));
+
+        format!(
+            concat!(
+                "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+                "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+                " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+                "  <rdf:Description rdf:about=\"\"",
+                " xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+                " xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">\n",
+                "{}",
+                "  </rdf:Description>\n",
+                " </rdf:RDF>\n",
+                "</x:xmpmeta>\n",
+                "<?xpacket end=\"w\"?>"
+            ),
+            properties
+        )
+    }
+
+    /// Add the fields to a PNG as text chunks, international ones for
+    /// values outside Latin-1
+    pub(crate) fn add_png_text<W: std::io::Write>(
+        &self,
+        encoder: &mut png::Encoder<'_, W>,
+    ) -> Result<(), png::EncodingError> {
+        for (key, value) in self.fields() {
+            if value.chars().all(|c| (c as u32) < 0x100) {
+                encoder.add_text_chunk(key.to_string(), value)?;
+            } else {
+                encoder.add_itxt_chunk(key.to_string(), value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `WinGIF <version>`
+fn software() -> String {
+    format!("WinGIF {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Escape text for XML element content
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// UTC date and time as RFC 3339, e.g. `2024-05-01T09:30:00Z`
+fn rfc3339(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Insert a comment extension and an XMP application extension after the
+/// header and global color table of the GIF in `data`
+pub(crate) fn embed_in_gif(data: &[u8], metadata: &EmbeddedMetadata) -> ExportResult<Vec<u8>> {
+    let invalid = || ExportError::MetadataEncode("not a GIF file".to_string());
+    if data.len() < 13 || !data.starts_with(b"GIF8") {
+        return Err(invalid());
+    }
+    let flags = data[10];
+    let color_table = if flags & 0x80 != 0 { 3 << ((flags & 0x07) + 1) } else { 0 };
+    let header_len = 13 + color_table;
+    if data.len() < header_len {
+        return Err(invalid());
+    }
+
+    let mut out = Vec::with_capacity(data.len() + 4096);
+    out.extend_from_slice(&data[..header_len]);
+
+    // Comment extension, split into sub-blocks of at most 255 bytes
+    out.extend_from_slice(&[0x21, 0xFE]);
+    for block in metadata.comment().as_bytes().chunks(255) {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0);
+
+    // XMP is stored raw; the "magic trailer" of descending lengths leads
+    // decoders that read it as sub-blocks back to the block terminator
+    out.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+    out.extend_from_slice(b"XMP DataXMP");
+    out.extend_from_slice(metadata.xmp().as_bytes());
+    out.push(0x01);
+    out.extend((0..=0xFFu8).rev());
+    out.push(0);
+
+    out.extend_from_slice(&data[header_len..]);
+    Ok(out)
+}
+
+/// Add an XMP chunk to the WebP in `data`
+///
+/// Simple-format files, which libwebp also writes for single-frame
+/// animations, are converted to the extended format first.
+pub(crate) fn embed_in_webp(data: &[u8], metadata: &EmbeddedMetadata) -> ExportResult<Vec<u8>> {
+    let invalid = || ExportError::MetadataEncode("not a WebP file".to_string());
+    if data.len() < 30 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err(invalid());
+    }
+
+    let mut out = data[..12].to_vec();
+    let canvas = match &data[12..16] {
+        b"VP8X" => None,
+        // Frame tag, start code, then 14-bit width and height
+        b"VP8 " => Some((
+            u16::from_le_bytes([data[26], data[27]]) as u32 & 0x3FFF,
+            u16::from_le_bytes([data[28], data[29]]) as u32 & 0x3FFF,
+            false,
+        )),
+        // Signature, then 14-bit width - 1, height - 1 and the alpha bit
+        b"VP8L" => {
+            let bits = u32::from_le_bytes([data[21], data[22], data[23], data[24]]);
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1, bits & (1 << 28) != 0))
+        }
+        _ => return Err(invalid()),
+    };
+    if let Some((width, height, alpha)) = canvas {
+        out.extend_from_slice(b"VP8X");
+        out.extend_from_slice(&10u32.to_le_bytes());
+        out.extend_from_slice(&[if alpha { 0x10 } else { 0 }, 0, 0, 0]);
+        out.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        out.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+    }
+    out.extend_from_slice(&data[12..]);
+    // VP8X flags: XMP metadata present
+    out[20] |= 0x04;
+
+    let xmp = metadata.xmp();
+    out.extend_from_slice(b"XMP ");
+    out.extend_from_slice(&(xmp.len() as u32).to_le_bytes());
+    out.extend_from_slice(xmp.as_bytes());
+    if xmp.len() % 2 == 1 {
+        out.push(0);
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Ok(out)
+}
+
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    wingif_version: &'static str,
+    capture: Option<SidecarCapture>,
+    frame_count: usize,
+    duration_ms: f64,
+    effective_fps: f64,
+    frame_timestamps_ms: Vec<f64>,
+    export: SidecarExport<'a>,
+}
+
+#[derive(Serialize)]
+struct SidecarCapture {
+    target: CaptureTargetKind,
+    region: CaptureRegion,
+    requested_fps: u8,
+}
+
+#[derive(Serialize)]
+struct SidecarExport<'a> {
+    format: &'a str,
+    output: &'a Path,
+    fps: f64,
+    options: &'a ExportOptions,
+}
+
+/// Path of the sidecar for `output`, e.g. `clip.gif.json`
+pub fn sidecar_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// Write the sidecar of an export in format `format` with `frame_count` frames
+/// and the settings `options`
+///
+/// Frames without a recorded capture time are placed at `config.fps`.
+pub(crate) fn write_sidecar(
+    format: &str,
+    frame_count: usize,
+    config: &ExportConfig,
+    options: &ExportOptions,
+) -> ExportResult<PathBuf> {
+    let capture = config.capture.as_ref();
+    let fps = if config.fps > 0.0 { config.fps } else { 15.0 };
+    let frame_timestamps_ms: Vec<f64> = (0..frame_count)
+        .map(|i| match capture.and_then(|c| c.frame_timestamps.get(i)) {
+            Some(t) => millis(*t),
+            None => millis(Duration::from_secs_f64(i as f64 / fps)),
+        })
+        .collect();
+
+    let (duration_ms, effective_fps) = match capture {
+        Some(c) if !c.duration.is_zero() => (
+            millis(c.duration),
+            frame_count as f64 / c.duration.as_secs_f64(),
+        ),
+        _ => (millis(Duration::from_secs_f64(frame_count as f64 / fps)), fps),
+    };
+
+    let sidecar = Sidecar {
+        wingif_version: env!("CARGO_PKG_VERSION"),
+        capture: capture.map(|c| SidecarCapture {
+            target: c.target,
+            region: c.region,
+            requested_fps: c.requested_fps,
+        }),
+        frame_count,
+        duration_ms,
+        effective_fps,
+        frame_timestamps_ms,
+        export: SidecarExport {
+            format,
+            output: &config.output_path,
+            fps: config.fps,
+            options,
+        },
+    };
+
+    let path = sidecar_path(&config.output_path);
+    let mut writer = BufWriter::new(File::create(&path)?);
+    serde_json::to_writer_pretty(&mut writer, &sidecar)
+        .map_err(|e| ExportError::MetadataEncode(e.to_string()))?;
+    writer.flush()?;
+    Ok(path)
+}
+
+/// Milliseconds, rounded to microseconds
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{ExportRegistry, OptionValue};
+    use crate::test_util::write_frames;
+
+    fn millis_list(values: &serde_json::Value) -> Vec<f64> {
+        values.as_array().unwrap().iter().map(|v| v.as_f64().unwrap()).collect()
+    }
+
+    #[test]
+    fn sidecar_path_appends_json() {
+        assert_eq!(sidecar_path(Path::new("out/clip.gif")), Path::new("out/clip.gif.json"));
+    }
+
+    #[test]
+    fn export_writes_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = write_frames(dir.path(), 3);
+        let output = dir.path().join("clip.png");
+        let mut options = ExportOptions::new();
+        options.set("width", OptionValue::Int(16));
+        let config = ExportConfig {
+            output_path: output.clone(),
+            fps: 10.0,
+            options,
+            capture: Some(CaptureInfo {
+                target: CaptureTargetKind::Window,
+                region: CaptureRegion { x: -8, y: 20, width: 32, height: 24 },
+                requested_fps: 10,
+                duration: Duration::from_millis(400),
+                frame_timestamps: [0, 150, 200].map(Duration::from_millis).to_vec(),
+            }),
+            sidecar: true,
+            ..Default::default()
+        };
+
+        let apng = ExportRegistry::default().get("apng").unwrap();
+        assert_eq!(apng.export(&frames, &config, None).unwrap(), output);
+
+        let json = std::fs::read(sidecar_path(&output)).unwrap();
+        let sidecar: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(sidecar["frame_count"], 3);
+        assert_eq!(millis_list(&sidecar["frame_timestamps_ms"]), [0.0, 150.0, 200.0]);
+        assert_eq!(sidecar["duration_ms"], 400.0);
+        assert_eq!(sidecar["effective_fps"], 7.5);
+        assert_eq!(sidecar["capture"]["target"], "window");
+        let region = &sidecar["capture"]["region"];
+        assert_eq!((&region["x"], &region["y"]), (&(-8).into(), &20.into()));
+        assert_eq!((&region["width"], &region["height"]), (&32.into(), &24.into()));
+        assert_eq!(sidecar["export"]["format"], "apng");
+        assert_eq!(sidecar["export"]["options"], serde_json::json!({ "width": 16 }));
+    }
+
+    #[test]
+    fn sidecar_without_capture_follows_fps() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ExportConfig {
+            output_path: dir.path().join("clip.gif"),
+            fps: 20.0,
+            ..Default::default()
+        };
+
+        let path = write_sidecar("gif", 4, &config, &ExportOptions::new()).unwrap();
+        let sidecar: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert!(sidecar["capture"].is_null());
+        assert_eq!(millis_list(&sidecar["frame_timestamps_ms"]), [0.0, 50.0, 100.0, 150.0]);
+        assert_eq!(sidecar["duration_ms"], 200.0);
+        assert_eq!(sidecar["effective_fps"], 20.0);
+    }
+
+    fn embedded() -> EmbeddedMetadata {
+        EmbeddedMetadata {
+            title: Some("Demo <1>".to_string()),
+            author: Some("Ann".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn gif_with_two_frames() -> Vec<u8> {
+        let palette = [0, 0, 0, 255, 255, 255];
+        let mut data = Vec::new();
+        let mut encoder = gif::Encoder::new(&mut data, 4, 2, &palette).unwrap();
+        for pixels in [[0, 1, 0, 1, 1, 0, 1, 0], [1, 1, 1, 1, 0, 0, 0, 0]] {
+            encoder.write_frame(&gif::Frame::from_indexed_pixels(4, 2, pixels, None)).unwrap();
+        }
+        drop(encoder);
+        data
+    }
+
+    /// Chunk FourCCs and sizes after the RIFF header, checking they add up
+    fn webp_chunks(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize, data.len() - 8);
+        let mut chunks = Vec::new();
+        let mut rest = &data[12..];
+        while !rest.is_empty() {
+            let size = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            let padded = 8 + size + size % 2;
+            assert!(padded <= rest.len(), "chunk overruns the file");
+            chunks.push((rest[..4].try_into().unwrap(), &rest[8..8 + size]));
+            rest = &rest[padded..];
+        }
+        chunks
+    }
+
+    /// Embed into `data` and check the chunk layout and that it still decodes
+    fn check_webp_embedding(data: &[u8], width: u32, height: u32) {
+        let out = embed_in_webp(data, &embedded()).unwrap();
+        let chunks = webp_chunks(&out);
+
+        let (kind, vp8x) = chunks[0];
+        assert_eq!(&kind, b"VP8X");
+        assert_eq!(vp8x[0] & 0x04, 0x04, "XMP flag");
+        let canvas = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) + 1;
+        assert_eq!((canvas(&vp8x[4..7]), canvas(&vp8x[7..10])), (width, height));
+
+        let (kind, xmp) = chunks[chunks.len() - 1];
+        assert_eq!(&kind, b"XMP ");
+        assert_eq!(xmp, embedded().xmp().as_bytes());
+        // Everything after the original header is carried over unchanged
+        let original = webp_chunks(data);
+        let carried = if &original[0].0 == b"VP8X" { &original[1..] } else { &original[..] };
+        assert_eq!(chunks[1..chunks.len() - 1], *carried);
+
+        let features = webp::BitstreamFeatures::new(&out).expect("decodable WebP");
+        assert_eq!((features.width(), features.height()), (width, height));
+    }
+
+    #[test]
+    fn embeds_into_gif() {
+        let data = gif_with_two_frames();
+        let out = embed_in_gif(&data, &embedded()).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(out.as_slice()).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push(frame.buffer.to_vec());
+        }
+        assert_eq!(frames, [[0, 1, 0, 1, 1, 0, 1, 0], [1, 1, 1, 1, 0, 0, 0, 0]]);
+
+        let xmp = String::from_utf8_lossy(decoder.xmp_metadata().expect("XMP extension"));
+        assert!(xmp.starts_with("<?xpacket begin="));
+        assert!(xmp.contains("Demo &lt;1&gt;"));
+
+        let comment = embedded().comment();
+        assert!(out.windows(comment.len()).any(|w| w == comment.as_bytes()));
+    }
+
+    #[test]
+    fn embeds_into_simple_lossless_webp() {
+        let pixels = crate::test_util::frame(0);
+        let data = webp::Encoder::from_rgba(pixels.as_raw(), 32, 24).encode_lossless();
+        assert_eq!(&data[12..16], b"VP8L");
+        check_webp_embedding(&data, 32, 24);
+    }
+
+    #[test]
+    fn embeds_into_simple_lossy_webp() {
+        let pixels = image::DynamicImage::ImageRgba8(crate::test_util::frame(0)).to_rgb8();
+        let data = webp::Encoder::from_rgb(pixels.as_raw(), 32, 24).encode(75.0);
+        assert_eq!(&data[12..16], b"VP8 ");
+        check_webp_embedding(&data, 32, 24);
+    }
+
+    #[test]
+    fn embeds_into_extended_webp() {
+        let config = webp::WebPConfig::new().unwrap();
+        let mut encoder = webp::AnimEncoder::new(32, 24, &config);
+        let frames = [crate::test_util::frame(0), crate::test_util::frame(1)];
+        for (i, frame) in frames.iter().enumerate() {
+            encoder.add_frame(webp::AnimFrame::from_rgba(frame.as_raw(), 32, 24, i as i32 * 100));
+        }
+        let data = encoder.encode();
+        assert_eq!(&data[12..16], b"VP8X");
+        check_webp_embedding(&data, 32, 24);
+    }
+
+    #[test]
+    fn rejects_foreign_and_truncated_input() {
+        let gif = gif_with_two_frames();
+        let webp = webp::Encoder::from_rgba(crate::test_util::frame(0).as_raw(), 32, 24)
+            .encode_lossless()
+            .to_vec();
+        let is_rejected =
+            |result: ExportResult<Vec<u8>>| matches!(result, Err(ExportError::MetadataEncode(_)));
+
+        for data in [&gif[..0], &gif[..12], &gif[..16], &webp[..]] {
+            assert!(is_rejected(embed_in_gif(data, &embedded())));
+        }
+        for data in [&webp[..0], &webp[..20], &webp[..29], &gif[..]] {
+            assert!(is_rejected(embed_in_webp(data, &embedded())));
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Several exports from one pass over the frames
+//!
+//! Every PNG is decoded once and handed to all outputs, each resizing and
+//! encoding it on its own. APNG and sprite sheets are told the frame count
+//! so they write as they go; WebP, whose encoder needs every frame at once,
+//! reads the PNGs again after the pass rather than holding a copy of each.
+
+use crate::cancel::CancelToken;
+use crate::metadata::{self, CaptureInfo};
+use crate::registry::{ExportConfig, ExportOptions};
+use crate::{
+    ApngExportConfig, ApngExporter, Av1ExportConfig, Av1Exporter, AvifExportConfig, AvifExporter,
+    ExportError, ExportResult, GifExportConfig, GifExporter, H264ExportConfig, H264Exporter,
+    HtmlExportConfig, HtmlExporter, ProgressCallback, SpriteSheetExportConfig,
+    SpriteSheetExporter, StoryboardExportConfig, StoryboardExporter, WebPExportConfig,
+    WebPExporter, Y4mExportConfig, Y4mExporter,
+};
+use image::RgbaImage;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A started exporter that takes frames one at a time
+pub(crate) trait FrameSink {
+    fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()>;
+    fn finish(self: Box<Self>) -> ExportResult<PathBuf>;
+
+    /// Stop without finishing; the caller removes the output file
+    fn abort(self: Box<Self>) {}
+}
+
+macro_rules! frame_sinks {
+    ($($exporter:ty $(=> $abort:path)?),* $(,)?) => {$(
+        impl FrameSink for $exporter {
+            fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+                <$exporter>::add_frame(self, image)
+            }
+
+            fn finish(self: Box<Self>) -> ExportResult<PathBuf> {
+                <$exporter>::finish(*self)
+            }
+
+            $(
+                fn abort(self: Box<Self>) {
+                    $abort(*self)
+                }
+            )?
+        }
+    )*};
+}
+
+/// Join the encoder threads before the output is removed
+fn abort_gif(mut exporter: GifExporter) {
+    exporter.abort();
+}
+
+frame_sinks!(
+    GifExporter => abort_gif,
+    ApngExporter,
+    WebPExporter,
+    AvifExporter,
+    H264Exporter,
+    Av1Exporter,
+    Y4mExporter,
+    SpriteSheetExporter => SpriteSheetExporter::abort,
+    HtmlExporter,
+    StoryboardExporter,
+);
+
+/// One output of [`export_multi`]
+#[derive(Debug, Clone)]
+pub enum ExportTarget {
+    Gif(GifExportConfig),
+    Apng(ApngExportConfig),
+    WebP(WebPExportConfig),
+    Avif(AvifExportConfig),
+    H264(H264ExportConfig),
+    Av1(Av1ExportConfig),
+    Y4m(Y4mExportConfig),
+    SpriteSheet(SpriteSheetExportConfig),
+    Html(HtmlExportConfig),
+    Storyboard(StoryboardExportConfig),
+}
+
+impl ExportTarget {
+    /// Exporter taking the frames of the pass, for `frame_count` frames;
+    /// `None` for targets encoded from the PNGs afterwards
+    fn start(self, frame_count: usize) -> ExportResult<Option<Box<dyn FrameSink>>> {
+        macro_rules! started {
+            ($exporter:expr) => {{
+                let mut exporter = $exporter;
+                exporter.start()?;
+                Some(Box::new(exporter) as Box<dyn FrameSink>)
+            }};
+        }
+        Ok(match self {
+            Self::Gif(config) => started!(GifExporter::new(config)?),
+            Self::Apng(config) => started!(ApngExporter::new(config)?.with_frame_count(frame_count)),
+            Self::WebP(_) => None,
+            Self::Avif(config) => started!(AvifExporter::new(config)?),
+            Self::H264(config) => started!(H264Exporter::new(config)?),
+            Self::Av1(config) => started!(Av1Exporter::new(config)?),
+            Self::Y4m(config) => started!(Y4mExporter::new(config)?),
+            Self::SpriteSheet(config) => {
+                started!(SpriteSheetExporter::new(config)?.with_frame_count(frame_count))
+            }
+            Self::Html(config) => started!(HtmlExporter::new(config)?),
+            Self::Storyboard(config) => started!(StoryboardExporter::new(config)?),
+        })
+    }
+
+    /// Registry id of the format, as recorded in the sidecar
+    fn format_id(&self) -> &'static str {
+        match self {
+            Self::Gif(_) => "gif",
+            Self::Apng(_) => "apng",
+            Self::WebP(_) => "webp",
+            Self::Avif(_) => "avif",
+            Self::H264(_) => "h264",
+            Self::Av1(_) => "av1",
+            Self::Y4m(_) => "y4m",
+            Self::SpriteSheet(_) => "sprite-sheet",
+            Self::Html(_) => "html",
+            Self::Storyboard(_) => "storyboard",
+        }
+    }
+
+    fn output_path(&self) -> &Path {
+        match self {
+            Self::Gif(config) => &config.output_path,
+            Self::Apng(config) => &config.output_path,
+            Self::WebP(config) => &config.output_path,
+            Self::Avif(config) => &config.output_path,
+            Self::H264(config) => &config.output_path,
+            Self::Av1(config) => &config.output_path,
+            Self::Y4m(config) => &config.output_path,
+            Self::SpriteSheet(config) => &config.output_path,
+            Self::Html(config) => &config.output_path,
+            Self::Storyboard(config) => &config.output_path,
+        }
+    }
+
+    fn fps(&self) -> f64 {
+        match self {
+            Self::Gif(config) => config.fps as f64,
+            Self::Apng(config) => config.fps as f64,
+            Self::WebP(config) => config.fps as f64,
+            Self::Avif(config) => config.fps as f64,
+            Self::H264(config) => config.fps as f64,
+            Self::Av1(config) => config.fps as f64,
+            Self::Y4m(config) => config.fps,
+            Self::SpriteSheet(config) => config.fps as f64,
+            Self::Html(config) => config.fps as f64,
+            Self::Storyboard(config) => config.fps as f64,
+        }
+    }
+}
+
+/// Settings shared by all targets of [`export_multi`]
+#[derive(Debug, Clone, Default)]
+pub struct MultiExportOptions {
+    /// Recording the frames came from, for the sidecars
+    pub capture: Option<CaptureInfo>,
+    /// Also write a `<output>.json` metadata sidecar for every output
+    pub sidecar: bool,
+    /// Stops the export between frames
+    pub cancel: CancelToken,
+}
+
+/// A started target of the pass
+struct Running {
+    target: ExportTarget,
+    sink: Option<Box<dyn FrameSink>>,
+}
+
+/// Export PNG files to every target, decoding each frame only once
+///
+/// Returns the output paths in the order of `targets`. On an error or
+/// cancellation every unfinished output is stopped and removed; outputs
+/// already finished are kept.
+pub fn export_multi(
+    png_paths: &[PathBuf],
+    targets: Vec<ExportTarget>,
+    options: &MultiExportOptions,
+    progress: Option<ProgressCallback>,
+) -> ExportResult<Vec<PathBuf>> {
+    if png_paths.is_empty() {
+        return Err(ExportError::NoFrames);
+    }
+
+    let frame_count = png_paths.len();
+    let mut running = Vec::with_capacity(targets.len());
+    for target in targets {
+        let started = target.clone().start(frame_count);
+        match started {
+            Ok(sink) => running.push(Running { target, sink }),
+            Err(e) => {
+                abort_all(running);
+                remove_output(&target);
+                return Err(e);
+            }
+        }
+    }
+
+    // The pass plus one more for every target encoded from the PNGs
+    let deferred = running.iter().filter(|r| r.sink.is_none()).count();
+    let steps = (frame_count * (1 + deferred)) as f32;
+    let report = |done: usize| {
+        if let Some(ref cb) = progress {
+            cb(done as f32 / steps);
+        }
+    };
+
+    if let Err(e) = feed_frames(png_paths, &mut running, options, &report) {
+        abort_all(running);
+        return Err(e);
+    }
+
+    let mut outputs = Vec::with_capacity(running.len());
+    let mut done = frame_count;
+    let mut pending = running.into_iter();
+    while let Some(Running { target, sink }) = pending.next() {
+        let result = if options.cancel.is_cancelled() {
+            Err(ExportError::Cancelled)
+        } else if let Some(sink) = sink {
+            sink.finish()
+        } else {
+            let output = finish_deferred(png_paths, &target);
+            done += frame_count;
+            report(done);
+            output
+        };
+
+        let result = result.and_then(|output| {
+            if options.sidecar && target.output_path() != Path::new("-") {
+                write_sidecar(&target, frame_count, options)?;
+            }
+            Ok(output)
+        });
+        match result {
+            Ok(output) => outputs.push(output),
+            Err(e) => {
+                remove_output(&target);
+                abort_all(pending.collect());
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(outputs)
+}
+
+/// Decode every PNG once and hand it to all sinks
+fn feed_frames(
+    png_paths: &[PathBuf],
+    running: &mut [Running],
+    options: &MultiExportOptions,
+    report: &dyn Fn(usize),
+) -> ExportResult<()> {
+    for (i, path) in png_paths.iter().enumerate() {
+        if options.cancel.is_cancelled() {
+            return Err(ExportError::Cancelled);
+        }
+
+        let img = image::open(path)?.to_rgba8();
+        let mut sinks: Vec<_> = running.iter_mut().filter_map(|r| r.sink.as_mut()).collect();
+        if let Some((last, rest)) = sinks.split_last_mut() {
+            for sink in rest {
+                sink.add_frame(img.clone())?;
+            }
+            last.add_frame(img)?;
+        }
+
+        report(i + 1);
+    }
+    Ok(())
+}
+
+/// Encode a target that skipped the pass
+fn finish_deferred(png_paths: &[PathBuf], target: &ExportTarget) -> ExportResult<PathBuf> {
+    match target {
+        ExportTarget::WebP(config) => WebPExporter::export_from_pngs(png_paths, config.clone(), None),
+        _ => Err(ExportError::NoFrames),
+    }
+}
+
+/// Stop every unfinished target and remove its output
+fn abort_all(running: Vec<Running>) {
+    for Running { target, sink } in running {
+        if let Some(sink) = sink {
+            sink.abort();
+        }
+        remove_output(&target);
+    }
+}
+
+/// Remove the output file of `target`; streams to stdout are left alone
+fn remove_output(target: &ExportTarget) {
+    if target.output_path() != Path::new("-") {
+        let _ = fs::remove_file(target.output_path());
+    }
+}
+
+/// Sidecar of one target, with the capture shared by all of them
+fn write_sidecar(
+    target: &ExportTarget,
+    frame_count: usize,
+    options: &MultiExportOptions,
+) -> ExportResult<PathBuf> {
+    let config = ExportConfig {
+        output_path: target.output_path().to_path_buf(),
+        fps: target.fps(),
+        capture: options.capture.clone(),
+        sidecar: true,
+        ..Default::default()
+    };
+    metadata::write_sidecar(target.format_id(), frame_count, &config, &ExportOptions::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::sidecar_path;
+    use crate::test_util::write_frames;
+    use image::codecs::gif::GifDecoder;
+    use image::codecs::png::PngDecoder;
+    use image::AnimationDecoder;
+    use std::io::Cursor;
+
+    fn targets(dir: &Path) -> Vec<ExportTarget> {
+        vec![
+            ExportTarget::Gif(GifExportConfig {
+                output_path: dir.join("clip.gif"),
+                ..Default::default()
+            }),
+            ExportTarget::WebP(WebPExportConfig {
+                output_path: dir.join("clip.webp"),
+                ..Default::default()
+            }),
+            ExportTarget::Apng(ApngExportConfig {
+                output_path: dir.join("clip.png"),
+                ..Default::default()
+            }),
+        ]
+    }
+
+    #[test]
+    fn exports_every_target_from_one_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = write_frames(dir.path(), 4);
+        let options = MultiExportOptions { sidecar: true, ..Default::default() };
+
+        let outputs = export_multi(&frames, targets(dir.path()), &options, None).unwrap();
+        let expected: Vec<_> =
+            targets(dir.path()).iter().map(|t| t.output_path().to_owned()).collect();
+        assert_eq!(outputs, expected);
+        for output in &outputs {
+            assert!(sidecar_path(output).exists());
+        }
+
+        let gif = GifDecoder::new(Cursor::new(fs::read(&outputs[0]).unwrap())).unwrap();
+        assert_eq!(gif.into_frames().count(), 4);
+        let webp = webp::BitstreamFeatures::new(&fs::read(&outputs[1]).unwrap()).unwrap();
+        assert!(webp.has_animation());
+        let apng = PngDecoder::new(Cursor::new(fs::read(&outputs[2]).unwrap())).unwrap();
+        assert!(apng.is_apng().unwrap());
+    }
+
+    #[test]
+    fn cancelling_leaves_no_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = write_frames(dir.path(), 4);
+        let options = MultiExportOptions { sidecar: true, ..Default::default() };
+        let cancel = options.cancel.clone();
+        // Cancel halfway through the pass
+        let progress = Box::new(move |p: f32| {
+            if p >= 0.25 {
+                cancel.cancel();
+            }
+        });
+
+        let result = export_multi(&frames, targets(dir.path()), &options, Some(progress));
+        assert!(matches!(result, Err(ExportError::Cancelled)));
+        for target in targets(dir.path()) {
+            assert!(!target.output_path().exists(), "{:?} left behind", target.output_path());
+            assert!(!sidecar_path(target.output_path()).exists());
+        }
+    }
+
+    #[test]
+    fn failing_frame_removes_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut frames = write_frames(dir.path(), 3);
+        frames.insert(2, dir.path().join("missing.png"));
+
+        let result =
+            export_multi(&frames, targets(dir.path()), &MultiExportOptions::default(), None);
+        assert!(result.is_err());
+        for target in targets(dir.path()) {
+            assert!(!target.output_path().exists(), "{:?} left behind", target.output_path());
+        }
+    }
+}
@@ -1,39 +1,234 @@
 //! PNG sequence export
 
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, frame_time, resize_to, save_jpeg, save_webp};
 use crate::{ExportError, ExportResult, ProgressCallback};
-use std::fs;
+use image::ImageFormat;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use zip::write::SimpleFileOptions;
+use zip::result::ZipError;
+use zip::{CompressionMethod, ZipWriter};
+
+/// `manifest.json` stored next to the frames in a ZIP export
+#[derive(Serialize)]
+struct SequenceManifest {
+    fps: u8,
+    frame_count: usize,
+    duration_ms: u64,
+    frames: Vec<ManifestFrame>,
+}
+
+#[derive(Serialize)]
+struct ManifestFrame {
+    file: String,
+    width: u32,
+    height: u32,
+    timestamp_ms: u64,
+}
+
+/// Image format of an exported sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Qoi,
+}
+
+impl SequenceFormat {
+    /// File extension without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Qoi => "qoi",
+        }
+    }
+
+    /// Guess the format from the extension of `path`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "webp" => Some(Self::WebP),
+            "qoi" => Some(Self::Qoi),
+            _ => None,
+        }
+    }
+}
+
+/// Image sequence export configuration
+///
+/// Files are named `{prefix}{index}.{ext}`, with the index counted from
+/// `start_index` and zero padded to `padding` digits.
+#[derive(Debug, Clone)]
+pub struct PngSequenceConfig {
+    pub output_dir: PathBuf,
+    pub prefix: String,
+    pub start_index: usize,
+    pub padding: usize,
+    pub format: SequenceFormat,
+    /// JPEG and WebP quality, 0-100
+    pub quality: u8,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Recorded frames to export, end exclusive; `None` for all
+    pub range: Option<Range<usize>>,
+}
+
+impl Default for PngSequenceConfig {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::new(),
+            prefix: "frame_".to_string(),
+            start_index: 0,
+            padding: 5,
+            format: SequenceFormat::Png,
+            quality: 90,
+            width: None,
+            height: None,
+            range: None,
+        }
+    }
+}
+
+impl PngSequenceConfig {
+    /// File name of the `n`th exported frame
+    pub fn file_name(&self, n: usize) -> String {
+        format!(
+            "{}{:0width$}.{}",
+            self.prefix,
+            self.start_index + n,
+            self.format.extension(),
+            width = self.padding
+        )
+    }
+}
 
 /// PNG sequence exporter
 pub struct PngExporter;
 
 impl PngExporter {
-    /// Copy PNG sequence to output directory
+    /// Write the frames into `config.output_dir`, renamed, converted and
+    /// resized as configured
+    ///
+    /// Unchanged PNGs are copied as they are.
     pub fn export(
         png_paths: &[PathBuf],
-        output_dir: &Path,
+        config: &PngSequenceConfig,
         progress: Option<ProgressCallback>,
     ) -> ExportResult<PathBuf> {
+        let png_paths = match config.range {
+            Some(ref range) => {
+                let end = range.end.min(png_paths.len());
+                png_paths.get(range.start..end).unwrap_or_default()
+            }
+            None => png_paths,
+        };
         if png_paths.is_empty() {
             return Err(ExportError::NoFrames);
         }
 
         // Create output directory
-        fs::create_dir_all(output_dir)?;
+        fs::create_dir_all(&config.output_dir)?;
+
+        let total = png_paths.len();
+        let resize = config.width.is_some() || config.height.is_some();
+        let mut size = None;
 
+        for (i, src_path) in png_paths.iter().enumerate() {
+            let dest_path = config.output_dir.join(config.file_name(i));
+
+            if config.format == SequenceFormat::Png && !resize {
+                fs::copy(src_path, &dest_path)?;
+            } else {
+                let img = image::open(src_path)?.to_rgba8();
+                let (width, height) = *size.get_or_insert_with(|| {
+                    fit_dimensions(img.width(), img.height(), config.width, config.height)
+                });
+                let img = resize_to(img, width, height);
+
+                match config.format {
+                    SequenceFormat::Png => img.save_with_format(&dest_path, ImageFormat::Png)?,
+                    SequenceFormat::Jpeg => save_jpeg(img, &dest_path, config.quality)?,
+                    SequenceFormat::WebP => save_webp(&img, &dest_path, config.quality)?,
+                    SequenceFormat::Qoi => img.save_with_format(&dest_path, ImageFormat::Qoi)?,
+                }
+            }
+
+            if let Some(ref cb) = progress {
+                cb((i + 1) as f32 / total as f32);
+            }
+        }
+
+        Ok(config.output_dir.clone())
+    }
+
+    /// Write the PNG sequence into a single ZIP archive with a `manifest.json`
+    ///
+    /// `timestamps` are the capture times of the frames, relative to the
+    /// first; frames without one are placed `1 / fps` apart.
+    pub fn export_zip(
+        png_paths: &[PathBuf],
+        output_path: &Path,
+        fps: u8,
+        timestamps: &[Duration],
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(output_path)?));
+        // PNG data is already deflated, so store it as is
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(true);
+
+        let zip_error = |e: ZipError| ExportError::ZipEncode(e.to_string());
         let total = png_paths.len();
+        let millis = |index| (frame_time(timestamps, fps, index) * 1000.0).round() as u64;
+        let mut frames = Vec::with_capacity(total);
 
         for (i, src_path) in png_paths.iter().enumerate() {
             let filename = format!("frame_{:05}.png", i);
-            let dest_path = output_dir.join(&filename);
-            fs::copy(src_path, &dest_path)?;
+            let (width, height) = image::image_dimensions(src_path)?;
+
+            zip.start_file(filename.as_str(), options).map_err(zip_error)?;
+            zip.write_all(&fs::read(src_path)?)?;
+
+            frames.push(ManifestFrame {
+                file: filename,
+                width,
+                height,
+                timestamp_ms: millis(i),
+            });
 
             if let Some(ref cb) = progress {
                 cb((i + 1) as f32 / total as f32);
             }
         }
 
-        Ok(output_dir.to_path_buf())
+        let manifest = SequenceManifest {
+            fps,
+            frame_count: total,
+            duration_ms: millis(total),
+            frames,
+        };
+        zip.start_file("manifest.json", options).map_err(zip_error)?;
+        let manifest = serde_json::to_vec_pretty(&manifest)
+            .map_err(|e| ExportError::ZipEncode(e.to_string()))?;
+        zip.write_all(&manifest)?;
+        zip.finish().map_err(zip_error)?.flush()?;
+
+        Ok(output_path.to_path_buf())
     }
 
     /// Get frame count from a directory
@@ -48,3 +243,198 @@ impl PngExporter {
         Ok(count)
     }
 }
+
+/// Zipped PNG sequence entry in the format registry
+pub(crate) struct PngZipFormat;
+
+impl Exporter for PngZipFormat {
+    fn id(&self) -> &'static str {
+        "png-zip"
+    }
+
+    fn name(&self) -> &'static str {
+        "PNG sequence (ZIP)"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["zip"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: false,
+            alpha: true,
+            variable_delays: true,
+            max_colors: None,
+            lossy: false,
+            lossless: true,
+        }
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        PngExporter::export_zip(
+            png_paths,
+            &config.output_path,
+            config.fps_u8(),
+            &config.frame_timestamps(png_paths.len()),
+            progress,
+        )
+    }
+}
+
+/// Image sequence entry in the format registry
+///
+/// Frames are written next to the chosen file, named after its stem and in
+/// the format of its extension.
+pub(crate) struct PngSequenceFormat;
+
+impl Exporter for PngSequenceFormat {
+    fn id(&self) -> &'static str {
+        "image-sequence"
+    }
+
+    fn name(&self) -> &'static str {
+        "Image sequence (PNG/JPEG/WebP/QOI)"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["png", "jpg", "jpeg", "webp", "qoi"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: false,
+            alpha: true,
+            variable_delays: true,
+            max_colors: None,
+            lossy: true,
+            lossless: true,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = PngSequenceConfig::default();
+        let mut options = vec![
+            OptionSpec::int("start_index", "Start number", 0, 1_000_000, 0),
+            OptionSpec::int("padding", "Digits", 1, 10, defaults.padding as i64),
+            OptionSpec::int("quality", "JPEG/WebP quality", 0, 100, defaults.quality as i64),
+            OptionSpec::int("first_frame", "First frame", 0, 1_000_000, 0),
+            OptionSpec::int("frame_count", "Frames (0 = all)", 0, 1_000_000, 0),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let defaults = PngSequenceConfig::default();
+        let options = &config.options;
+        let path = &config.output_path;
+
+        let first = options.int_or("first_frame", 0).max(0) as usize;
+        let range = match options.int_or("frame_count", 0).max(0) as usize {
+            0 if first == 0 => None,
+            0 => Some(first..usize::MAX),
+            count => Some(first..first.saturating_add(count)),
+        };
+        let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+        let sequence_config = PngSequenceConfig {
+            output_dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            prefix: stem.map_or(defaults.prefix, |stem| format!("{}_", stem)),
+            start_index: options.int_or("start_index", 0).max(0) as usize,
+            padding: options.int_or("padding", defaults.padding as i64).clamp(1, 10) as usize,
+            format: SequenceFormat::from_path(path).unwrap_or(SequenceFormat::Png),
+            quality: options.u8_or("quality", defaults.quality),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+            range,
+        };
+        PngExporter::export(png_paths, &sequence_config, progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{CaptureInfo, CaptureRegion, CaptureTargetKind};
+    use crate::registry::ExportRegistry;
+    use crate::test_util::write_frames;
+    use std::io::Read;
+
+    fn manifest(zip_path: &Path) -> serde_json::Value {
+        let mut archive = zip::ZipArchive::new(File::open(zip_path).unwrap()).unwrap();
+        let mut json = String::new();
+        archive.by_name("manifest.json").unwrap().read_to_string(&mut json).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn timestamps_ms(manifest: &serde_json::Value) -> Vec<u64> {
+        let frames = manifest["frames"].as_array().unwrap();
+        frames.iter().map(|f| f["timestamp_ms"].as_u64().unwrap()).collect()
+    }
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&v| Duration::from_millis(v)).collect()
+    }
+
+    #[test]
+    fn manifest_uses_capture_timestamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = write_frames(dir.path(), 3);
+        let output = dir.path().join("frames.zip");
+
+        PngExporter::export_zip(&frames, &output, 10, &ms(&[0, 40, 130]), None).unwrap();
+        let manifest = manifest(&output);
+        assert_eq!(manifest["frame_count"], 3);
+        assert_eq!(timestamps_ms(&manifest), [0, 40, 130]);
+        // The last frame is shown for one interval at `fps`
+        assert_eq!(manifest["duration_ms"], 230);
+        assert_eq!(manifest["frames"][2]["file"], "frame_00002.png");
+    }
+
+    #[test]
+    fn frames_past_timestamps_follow_fps() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = write_frames(dir.path(), 4);
+        let output = dir.path().join("frames.zip");
+
+        PngExporter::export_zip(&frames, &output, 10, &ms(&[0, 40]), None).unwrap();
+        let manifest = manifest(&output);
+        assert_eq!(timestamps_ms(&manifest), [0, 40, 140, 240]);
+        assert_eq!(manifest["duration_ms"], 340);
+    }
+
+    #[test]
+    fn mismatched_capture_timestamps_fall_back_to_fps() {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = write_frames(dir.path(), 3);
+        let output = dir.path().join("frames.zip");
+        let config = ExportConfig {
+            output_path: output.clone(),
+            fps: 10.0,
+            capture: Some(CaptureInfo {
+                target: CaptureTargetKind::Monitor,
+                region: CaptureRegion::default(),
+                requested_fps: 10,
+                duration: Duration::from_millis(500),
+                frame_timestamps: ms(&[0, 40, 130, 170]),
+            }),
+            ..Default::default()
+        };
+
+        let zip = ExportRegistry::default().get("png-zip").unwrap();
+        zip.export(&frames, &config, None).unwrap();
+        let manifest = manifest(&output);
+        assert_eq!(timestamps_ms(&manifest), [0, 100, 200]);
+        assert_eq!(manifest["duration_ms"], 300);
+    }
+}
@@ -0,0 +1,280 @@
+//! Export presets for common sharing destinations
+//!
+//! Each preset bundles an output format with the destination's limits on
+//! size, frame rate, length and file size.
+
+use crate::gif::GifExportConfig;
+use crate::registry::{ExportOptions, OptionValue};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const MB: u64 = 1_000_000;
+
+/// Output format and limits of a sharing destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportPreset {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// Registry id of the output format
+    pub format: &'static str,
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Faster recordings drop frames down to this rate
+    pub max_fps: u8,
+    /// Longer recordings are trimmed at the end
+    pub max_duration: Option<Duration>,
+    /// Largest accepted file in bytes, reached with the GIF target size mode
+    pub max_size: Option<u64>,
+}
+
+/// Built-in presets
+pub const PRESETS: &[ExportPreset] = &[
+    ExportPreset {
+        id: "github-readme",
+        name: "GitHub README",
+        format: "gif",
+        max_width: 880,
+        max_height: 660,
+        max_fps: 15,
+        max_duration: Some(Duration::from_secs(30)),
+        max_size: Some(10 * MB),
+    },
+    ExportPreset {
+        id: "slack",
+        name: "Slack",
+        format: "gif",
+        max_width: 640,
+        max_height: 480,
+        max_fps: 15,
+        max_duration: Some(Duration::from_secs(30)),
+        max_size: Some(5 * MB),
+    },
+    ExportPreset {
+        id: "jira",
+        name: "Jira attachment",
+        format: "gif",
+        max_width: 1280,
+        max_height: 720,
+        max_fps: 10,
+        max_duration: Some(Duration::from_secs(60)),
+        max_size: Some(10 * MB),
+    },
+    ExportPreset {
+        id: "discord",
+        name: "Discord",
+        format: "gif",
+        max_width: 800,
+        max_height: 600,
+        max_fps: 15,
+        max_duration: Some(Duration::from_secs(30)),
+        max_size: Some(10 * MB),
+    },
+    ExportPreset {
+        id: "twitter",
+        name: "Twitter/X",
+        format: "gif",
+        max_width: 1280,
+        max_height: 1080,
+        max_fps: 15,
+        max_duration: Some(Duration::from_secs(15)),
+        max_size: Some(15 * MB),
+    },
+    ExportPreset {
+        id: "docs",
+        name: "Documentation site",
+        format: "webp",
+        max_width: 960,
+        max_height: 720,
+        max_fps: 20,
+        max_duration: Some(Duration::from_secs(60)),
+        max_size: None,
+    },
+];
+
+/// Way a recording breaks a preset's limits, and what export does about it
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresetWarning {
+    /// Trimmed to the limit
+    Duration { actual: Duration, limit: Duration },
+    /// Scaled down to fit
+    Dimensions { width: u32, height: u32, max_width: u32, max_height: u32 },
+    /// Frames dropped down to the limit
+    FrameRate { actual: f64, limit: u8 },
+}
+
+impl fmt::Display for PresetWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duration { actual, limit } => write!(
+                f,
+                "{:.1} s exceeds {} s limit, will be trimmed",
+                actual.as_secs_f64(),
+                limit.as_secs()
+            ),
+            Self::Dimensions { width, height, max_width, max_height } => write!(
+                f,
+                "{}x{} exceeds {}x{}, will be scaled down",
+                width, height, max_width, max_height
+            ),
+            Self::FrameRate { actual, limit } => write!(
+                f,
+                "{:.0} fps exceeds {} fps limit, frames will be dropped",
+                actual, limit
+            ),
+        }
+    }
+}
+
+impl ExportPreset {
+    /// Look up a built-in preset
+    pub fn get(id: &str) -> Option<&'static ExportPreset> {
+        PRESETS.iter().find(|p| p.id == id)
+    }
+
+    /// Check a recording against the limits before encoding
+    pub fn check(&self, width: u32, height: u32, duration: Duration, fps: f64) -> Vec<PresetWarning> {
+        let mut warnings = Vec::new();
+        if let Some(limit) = self.max_duration.filter(|&limit| duration > limit) {
+            warnings.push(PresetWarning::Duration { actual: duration, limit });
+        }
+        if width > self.max_width || height > self.max_height {
+            warnings.push(PresetWarning::Dimensions {
+                width,
+                height,
+                max_width: self.max_width,
+                max_height: self.max_height,
+            });
+        }
+        if fps > self.max_fps as f64 + 0.5 {
+            warnings.push(PresetWarning::FrameRate { actual: fps, limit: self.max_fps });
+        }
+        warnings
+    }
+
+    /// Indices of the frames to export, given each frame's timestamp
+    ///
+    /// Frames past the duration limit are cut and the rest thinned out to
+    /// at most `max_fps`.
+    pub fn select_frames(&self, timestamps: &[Duration]) -> Vec<usize> {
+        let interval = 1.0 / self.max_fps.max(1) as f64;
+        let mut next = 0.0;
+        let mut selected = Vec::new();
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            if self.max_duration.is_some_and(|limit| *timestamp >= limit) {
+                break;
+            }
+            // A little slack so capture jitter doesn't skip a whole frame
+            let secs = timestamp.as_secs_f64();
+            if secs + interval / 4.0 >= next {
+                selected.push(i);
+                next = (next + interval).max(secs + interval / 2.0);
+            }
+        }
+        selected
+    }
+
+    /// Set the preset's size limits in options of its format
+    pub fn apply(&self, options: &mut ExportOptions) {
+        options.set("width", OptionValue::Int(self.max_width as i64));
+        options.set("height", OptionValue::Int(self.max_height as i64));
+        if let (Some(max_size), "gif") = (self.max_size, self.format) {
+            options.set("max_size_kb", OptionValue::Int((max_size / 1024) as i64));
+        }
+    }
+
+    /// GIF settings within the preset's limits
+    ///
+    /// Pair with [`GifExporter::export_to_size`](crate::GifExporter::export_to_size)
+    /// and `max_size` to also meet the file size limit.
+    pub fn gif_config(&self, output_path: PathBuf, fps: u8) -> GifExportConfig {
+        GifExportConfig {
+            output_path,
+            fps: fps.min(self.max_fps),
+            width: Some(self.max_width),
+            height: Some(self.max_height),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&v| Duration::from_millis(v)).collect()
+    }
+
+    #[test]
+    fn recording_within_limits_has_no_warnings() {
+        let slack = ExportPreset::get("slack").unwrap();
+        assert_eq!(slack.check(640, 480, Duration::from_secs(30), 15.4), []);
+    }
+
+    #[test]
+    fn reports_every_exceeded_limit() {
+        let slack = ExportPreset::get("slack").unwrap();
+        let warnings = slack.check(1920, 400, Duration::from_secs(45), 30.0);
+        assert_eq!(
+            warnings,
+            [
+                PresetWarning::Duration {
+                    actual: Duration::from_secs(45),
+                    limit: Duration::from_secs(30)
+                },
+                PresetWarning::Dimensions {
+                    width: 1920,
+                    height: 400,
+                    max_width: 640,
+                    max_height: 480
+                },
+                PresetWarning::FrameRate { actual: 30.0, limit: 15 },
+            ]
+        );
+        assert_eq!(
+            slack.check(600, 481, Duration::ZERO, 16.0),
+            [
+                PresetWarning::Dimensions {
+                    width: 600,
+                    height: 481,
+                    max_width: 640,
+                    max_height: 480
+                },
+                PresetWarning::FrameRate { actual: 16.0, limit: 15 },
+            ]
+        );
+    }
+
+    #[test]
+    fn thins_uneven_frames_to_max_fps() {
+        // 10 fps: one frame per 100 ms, with a quarter interval of slack
+        let jira = ExportPreset::get("jira").unwrap();
+        let timestamps = ms(&[0, 30, 60, 95, 140, 200, 210, 290, 400]);
+        assert_eq!(jira.select_frames(&timestamps), [0, 3, 5, 7, 8]);
+
+        // Slower recordings keep every frame
+        let timestamps = ms(&[0, 150, 400, 520, 900]);
+        assert_eq!(jira.select_frames(&timestamps), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn cuts_frames_past_duration_limit() {
+        let twitter = ExportPreset::get("twitter").unwrap();
+        let timestamps = ms(&[0, 5_000, 14_900, 15_000, 16_000]);
+        assert_eq!(twitter.select_frames(&timestamps), [0, 1, 2]);
+    }
+
+    #[test]
+    fn applies_size_limit_only_to_gif() {
+        let mut options = ExportOptions::new();
+        ExportPreset::get("slack").unwrap().apply(&mut options);
+        assert_eq!(options.int_or("width", 0), 640);
+        assert_eq!(options.int_or("max_size_kb", 0), 4882);
+
+        let mut options = ExportOptions::new();
+        ExportPreset::get("docs").unwrap().apply(&mut options);
+        assert_eq!(options.int_or("height", 0), 720);
+        assert_eq!(options.get("max_size_kb"), None);
+    }
+}
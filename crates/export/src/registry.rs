@@ -0,0 +1,458 @@
+//! Format registry
+//!
+//! Every output format implements [`Exporter`] and describes its capabilities
+//! and options, so the UI and automation can list formats without knowing them.
+
+use crate::cancel::CancelToken;
+use crate::metadata::{self, CaptureInfo, EmbeddedMetadata};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What a format can represent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatCapabilities {
+    pub animated: bool,
+    pub alpha: bool,
+    /// Per-frame delays instead of a fixed frame rate
+    pub variable_delays: bool,
+    /// Palette size limit; `None` for full color
+    pub max_colors: Option<u32>,
+    pub lossy: bool,
+    pub lossless: bool,
+}
+
+/// Type and default of a format option
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionKind {
+    Bool { default: bool },
+    Int { min: i64, max: i64, default: i64 },
+    Choice { choices: &'static [&'static str], default: &'static str },
+}
+
+/// One entry of a format's config schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionSpec {
+    /// Key in [`ExportOptions`]
+    pub key: &'static str,
+    pub label: &'static str,
+    pub kind: OptionKind,
+}
+
+impl OptionSpec {
+    pub fn bool(key: &'static str, label: &'static str, default: bool) -> Self {
+        Self { key, label, kind: OptionKind::Bool { default } }
+    }
+
+    pub fn int(key: &'static str, label: &'static str, min: i64, max: i64, default: i64) -> Self {
+        Self { key, label, kind: OptionKind::Int { min, max, default } }
+    }
+
+    pub fn choice(
+        key: &'static str,
+        label: &'static str,
+        choices: &'static [&'static str],
+        default: &'static str,
+    ) -> Self {
+        Self { key, label, kind: OptionKind::Choice { choices, default } }
+    }
+
+    /// Maximum output width/height, 0 keeping the original size
+    pub(crate) fn size_limits() -> [Self; 2] {
+        [
+            Self::int("width", "Max width (0 = original)", 0, 16384, 0),
+            Self::int("height", "Max height (0 = original)", 0, 16384, 0),
+        ]
+    }
+
+    pub fn default_value(&self) -> OptionValue {
+        match self.kind {
+            OptionKind::Bool { default } => OptionValue::Bool(default),
+            OptionKind::Int { default, .. } => OptionValue::Int(default),
+            OptionKind::Choice { default, .. } => OptionValue::Choice(default.to_string()),
+        }
+    }
+}
+
+/// Value of a single option
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum OptionValue {
+    Bool(bool),
+    Int(i64),
+    Choice(String),
+}
+
+/// Option values keyed by [`OptionSpec::key`]
+///
+/// Missing or mistyped entries fall back to the format's own defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ExportOptions {
+    values: BTreeMap<String, OptionValue>,
+}
+
+impl ExportOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All defaults of a schema
+    pub fn from_schema(specs: &[OptionSpec]) -> Self {
+        let mut options = Self::new();
+        for spec in specs {
+            options.set(spec.key, spec.default_value());
+        }
+        options
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: OptionValue) {
+        self.values.insert(key.into(), value);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&OptionValue> {
+        self.values.get(key)
+    }
+
+    pub fn bool_or(&self, key: &str, default: bool) -> bool {
+        match self.values.get(key) {
+            Some(OptionValue::Bool(v)) => *v,
+            _ => default,
+        }
+    }
+
+    pub fn int_or(&self, key: &str, default: i64) -> i64 {
+        match self.values.get(key) {
+            Some(OptionValue::Int(v)) => *v,
+            _ => default,
+        }
+    }
+
+    pub fn choice_or<'a>(&'a self, key: &str, default: &'a str) -> &'a str {
+        match self.values.get(key) {
+            Some(OptionValue::Choice(v)) => v,
+            _ => default,
+        }
+    }
+
+    /// A size limit where 0 or a missing value means unconstrained
+    pub(crate) fn dimension(&self, key: &str) -> Option<u32> {
+        u32::try_from(self.int_or(key, 0)).ok().filter(|&v| v > 0)
+    }
+
+    /// An integer clamped into `u8`
+    pub(crate) fn u8_or(&self, key: &str, default: u8) -> u8 {
+        self.int_or(key, default as i64).clamp(0, u8::MAX as i64) as u8
+    }
+
+    /// An integer clamped into `u32`
+    pub(crate) fn u32_or(&self, key: &str, default: u32) -> u32 {
+        self.int_or(key, default as i64).clamp(0, u32::MAX as i64) as u32
+    }
+}
+
+/// Format-independent export settings
+#[derive(Debug, Clone)]
+pub struct ExportConfig {
+    pub output_path: PathBuf,
+    /// Frame rate, may be fractional (e.g. frames / recording duration)
+    pub fps: f64,
+    pub options: ExportOptions,
+    /// Recording the frames came from, if known
+    pub capture: Option<CaptureInfo>,
+    /// Also write a `<output>.json` metadata sidecar
+    pub sidecar: bool,
+    /// Fields to embed in the output, for formats that can hold them
+    pub metadata: EmbeddedMetadata,
+    /// Stops GIF encoding midway; other formats finish encoding and then
+    /// discard the result
+    pub cancel: CancelToken,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15.0,
+            options: ExportOptions::new(),
+            capture: None,
+            sidecar: false,
+            metadata: EmbeddedMetadata::default(),
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+impl ExportConfig {
+    /// Frame rate rounded for formats that only take whole numbers
+    pub fn fps_u8(&self) -> u8 {
+        if self.fps.is_finite() {
+            self.fps.round().clamp(1.0, u8::MAX as f64) as u8
+        } else {
+            15
+        }
+    }
+
+    /// Capture time of each of `frame_count` frames, or nothing when the
+    /// recording's timestamps don't line up with the frames
+    pub(crate) fn frame_timestamps(&self, frame_count: usize) -> Vec<Duration> {
+        self.capture
+            .as_ref()
+            .map(|c| c.frame_timestamps.clone())
+            .filter(|t| t.len() == frame_count)
+            .unwrap_or_default()
+    }
+}
+
+/// An output format
+pub trait Exporter: Send + Sync {
+    /// Stable identifier, e.g. `"gif"`
+    fn id(&self) -> &'static str;
+
+    /// Display name
+    fn name(&self) -> &'static str;
+
+    /// File extensions without the dot; the first one is the default
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn capabilities(&self) -> FormatCapabilities;
+
+    /// Config schema; values are passed in [`ExportConfig::options`]
+    fn options(&self) -> Vec<OptionSpec> {
+        Vec::new()
+    }
+
+    /// Encode a recorded PNG sequence
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf>;
+
+    /// Encode a recorded PNG sequence, then write the sidecar if requested
+    ///
+    /// Cancellation during encoding removes the output and returns
+    /// [`ExportError::Cancelled`].
+    fn export(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let output = self.encode(png_paths, config, progress)?;
+        finish_export(self.id(), output, png_paths.len(), config, &config.options)
+    }
+}
+
+/// Handle cancellation and write the sidecar once `format` has encoded
+/// `output`, recording `options` as the settings used
+pub(crate) fn finish_export(
+    format: &str,
+    output: PathBuf,
+    frame_count: usize,
+    config: &ExportConfig,
+    options: &ExportOptions,
+) -> ExportResult<PathBuf> {
+    if config.cancel.is_cancelled() {
+        if config.output_path != Path::new("-") {
+            let _ = fs::remove_file(&output);
+        }
+        return Err(ExportError::Cancelled);
+    }
+    // Streams to stdout have no file to sit next to
+    if config.sidecar && config.output_path != Path::new("-") {
+        metadata::write_sidecar(format, frame_count, config, options)?;
+    }
+    Ok(output)
+}
+
+/// Set of available output formats
+pub struct ExportRegistry {
+    exporters: Vec<Arc<dyn Exporter>>,
+}
+
+impl ExportRegistry {
+    /// Registry with all built-in formats
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(crate::gif::GifFormat);
+        registry.register(crate::apng::ApngFormat);
+        registry.register(crate::webp::WebPFormat);
+        registry.register(crate::video::AvifFormat);
+        registry.register(crate::video::H264Format);
+        registry.register(crate::video::Av1Format);
+        registry.register(crate::y4m::Y4mFormat);
+        registry.register(crate::html::HtmlFormat);
+        registry.register(crate::png::PngZipFormat);
+        registry.register(crate::png::PngSequenceFormat);
+        registry.register(crate::sprite::SpriteSheetFormat);
+        registry.register(crate::storyboard::StoryboardFormat);
+        registry.register(crate::still::StillFormat);
+        registry
+    }
+
+    /// Registry without any formats, to fill with [`register`](Self::register)
+    pub fn empty() -> Self {
+        Self { exporters: Vec::new() }
+    }
+
+    /// Add a format, replacing any format with the same id
+    pub fn register(&mut self, exporter: impl Exporter + 'static) {
+        let exporter: Arc<dyn Exporter> = Arc::new(exporter);
+        match self.exporters.iter().position(|e| e.id() == exporter.id()) {
+            Some(i) => self.exporters[i] = exporter,
+            None => self.exporters.push(exporter),
+        }
+    }
+
+    /// All formats in registration order
+    pub fn formats(&self) -> impl Iterator<Item = &dyn Exporter> {
+        self.exporters.iter().map(|e| e.as_ref())
+    }
+
+    /// Look up a format by id
+    pub fn get(&self, id: &str) -> Option<Arc<dyn Exporter>> {
+        self.exporters.iter().find(|e| e.id() == id).cloned()
+    }
+
+    /// Default format for the extension of `path`
+    ///
+    /// Extensions claimed by several formats go to the owner listed in
+    /// [`DEFAULT_FORMATS`]; any other extension goes to the first registered
+    /// format claiming it. Use [`formats_for_path`](Self::formats_for_path)
+    /// to offer the alternatives.
+    pub fn for_path(&self, path: &Path) -> Option<Arc<dyn Exporter>> {
+        let ext = extension_of(path)?;
+        let candidates = self.formats_for_path(path);
+        DEFAULT_FORMATS
+            .iter()
+            .filter(|(e, _)| *e == ext)
+            .find_map(|(_, id)| candidates.iter().find(|c| c.id() == *id))
+            .or_else(|| candidates.first())
+            .cloned()
+    }
+
+    /// Every format that claims the extension of `path`, in registration order
+    pub fn formats_for_path(&self, path: &Path) -> Vec<Arc<dyn Exporter>> {
+        let Some(ext) = extension_of(path) else {
+            return Vec::new();
+        };
+        self.exporters
+            .iter()
+            .filter(|e| e.extensions().contains(&ext.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Owner of each extension that several built-in formats claim, as
+/// (extension, format id)
+///
+/// `.png` stays animated, while `.jpg` and `.jpeg` are single frames since
+/// a sequence needs a directory of them.
+pub const DEFAULT_FORMATS: &[(&str, &str)] = &[
+    ("png", "apng"),
+    ("webp", "webp"),
+    ("jpg", "still"),
+    ("jpeg", "still"),
+];
+
+fn extension_of(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_ascii_lowercase())
+}
+
+impl Default for ExportRegistry {
+    /// Same as [`ExportRegistry::new`]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::sidecar_path;
+
+    #[test]
+    fn finish_export_writes_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("clip.gif");
+        fs::write(&output, b"GIF89a").unwrap();
+        let config =
+            ExportConfig { output_path: output.clone(), sidecar: true, ..Default::default() };
+
+        assert_eq!(
+            finish_export("gif", output.clone(), 2, &config, &config.options).unwrap(),
+            output
+        );
+        assert!(sidecar_path(&output).exists());
+    }
+
+    #[test]
+    fn finish_export_skips_sidecar_for_stdout() {
+        let config = ExportConfig { output_path: "-".into(), sidecar: true, ..Default::default() };
+
+        let output = finish_export("y4m", "-".into(), 2, &config, &config.options).unwrap();
+        assert_eq!(output, Path::new("-"));
+        assert!(!sidecar_path(Path::new("-")).exists());
+    }
+
+    #[test]
+    fn finish_export_removes_cancelled_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("clip.gif");
+        fs::write(&output, b"GIF89a").unwrap();
+        let config =
+            ExportConfig { output_path: output.clone(), sidecar: true, ..Default::default() };
+        config.cancel.cancel();
+
+        let result = finish_export("gif", output.clone(), 2, &config, &config.options);
+        assert!(matches!(result, Err(ExportError::Cancelled)));
+        assert!(!output.exists());
+        assert!(!sidecar_path(&output).exists());
+    }
+
+    #[test]
+    fn new_and_default_list_the_same_formats() {
+        let ids =
+            |registry: &ExportRegistry| registry.formats().map(|f| f.id()).collect::<Vec<_>>();
+        assert!(ids(&ExportRegistry::new()).contains(&"gif"));
+        assert_eq!(ids(&ExportRegistry::new()), ids(&ExportRegistry::default()));
+        assert!(ids(&ExportRegistry::empty()).is_empty());
+    }
+
+    #[test]
+    fn shared_extensions_go_to_their_default_format() {
+        let registry = ExportRegistry::new();
+        let id = |path: &str| registry.for_path(Path::new(path)).map(|f| f.id());
+        assert_eq!(id("clip.png"), Some("apng"));
+        assert_eq!(id("Frame.JPG"), Some("still"));
+        assert_eq!(id("frame.jpeg"), Some("still"));
+        assert_eq!(id("clip.webp"), Some("webp"));
+        assert_eq!(id("clip.gif"), Some("gif"));
+        assert_eq!(id("clip.unknown"), None);
+        assert_eq!(id("no_extension"), None);
+
+        for (ext, format) in DEFAULT_FORMATS {
+            let path = format!("clip.{}", ext);
+            let claimants = registry.formats_for_path(Path::new(&path));
+            assert!(claimants.len() > 1, "{} is only claimed once", ext);
+            assert!(claimants.iter().any(|f| f.id() == *format));
+            assert_eq!(id(&path), Some(*format));
+        }
+        assert!(registry.formats_for_path(Path::new("clip.unknown")).is_empty());
+    }
+
+    #[test]
+    fn default_owner_wins_over_registration_order() {
+        let mut registry = ExportRegistry::empty();
+        registry.register(crate::png::PngSequenceFormat);
+        registry.register(crate::apng::ApngFormat);
+        assert_eq!(registry.for_path(Path::new("a.png")).unwrap().id(), "apng");
+    }
+}
@@ -0,0 +1,503 @@
+//! Sprite sheet export
+//!
+//! Packs frames row by row into one or more atlas PNGs and writes a JSON
+//! descriptor plus a ready-to-use CSS animation next to them.
+
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, frame_time, resize_to};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use image::{imageops, RgbaImage};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Sprite sheet export configuration
+#[derive(Debug, Clone)]
+pub struct SpriteSheetExportConfig {
+    /// Atlas image path; the `.json` and `.css` files are written beside it
+    pub output_path: PathBuf,
+    pub fps: u8,
+    /// Capture time of each frame, relative to the first; frames past its
+    /// end, or all frames when empty, follow `fps`
+    pub timestamps: Vec<Duration>,
+    /// Frames per row; `None` aims for a square sheet
+    pub columns: Option<u32>,
+    /// Gap between frames in pixels
+    pub padding: u32,
+    /// Maximum atlas width and height; extra frames go into further sheets
+    pub max_texture_size: u32,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Default for SpriteSheetExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            timestamps: Vec::new(),
+            columns: None,
+            padding: 0,
+            max_texture_size: 4096,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SpriteSheetDescriptor {
+    frame_width: u32,
+    frame_height: u32,
+    fps: u8,
+    duration_ms: u64,
+    sheets: Vec<SheetInfo>,
+    frames: Vec<FrameInfo>,
+}
+
+#[derive(Serialize)]
+struct SheetInfo {
+    image: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize)]
+struct FrameInfo {
+    sheet: usize,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    delay_ms: u32,
+}
+
+/// Grid placement shared by all sheets
+struct SpriteLayout {
+    frame_width: u32,
+    frame_height: u32,
+    padding: u32,
+    columns: u32,
+    frames_per_sheet: usize,
+    frame_count: usize,
+}
+
+impl SpriteLayout {
+    fn new(
+        frame_width: u32,
+        frame_height: u32,
+        frame_count: usize,
+        config: &SpriteSheetExportConfig,
+    ) -> ExportResult<Self> {
+        let max = config.max_texture_size;
+        let padding = config.padding;
+        let fit_columns = (max + padding) / (frame_width + padding);
+        let fit_rows = (max + padding) / (frame_height + padding);
+        if fit_columns == 0 || fit_rows == 0 {
+            return Err(ExportError::SpriteEncode(format!(
+                "{}x{} frames do not fit into a {} px texture",
+                frame_width, frame_height, max
+            )));
+        }
+
+        let square = (frame_count as f64).sqrt().ceil() as u32;
+        let columns = config
+            .columns
+            .unwrap_or(square)
+            .clamp(1, fit_columns)
+            .min(frame_count as u32)
+            .max(1);
+
+        Ok(Self {
+            frame_width,
+            frame_height,
+            padding,
+            columns,
+            frames_per_sheet: (columns * fit_rows) as usize,
+            frame_count,
+        })
+    }
+
+    fn sheet_count(&self) -> usize {
+        self.frame_count.div_ceil(self.frames_per_sheet)
+    }
+
+    fn sheet_size(&self, sheet: usize) -> (u32, u32) {
+        let frames = (self.frame_count - sheet * self.frames_per_sheet).min(self.frames_per_sheet) as u32;
+        let columns = frames.min(self.columns);
+        let rows = frames.div_ceil(self.columns);
+        (
+            columns * self.frame_width + (columns - 1) * self.padding,
+            rows * self.frame_height + (rows - 1) * self.padding,
+        )
+    }
+
+    /// Sheet index and top-left corner of frame `index`
+    fn position(&self, index: usize) -> (usize, u32, u32) {
+        let sheet = index / self.frames_per_sheet;
+        let local = (index % self.frames_per_sheet) as u32;
+        let x = (local % self.columns) * (self.frame_width + self.padding);
+        let y = (local / self.columns) * (self.frame_height + self.padding);
+        (sheet, x, y)
+    }
+}
+
+/// Sprite sheet exporter
+pub struct SpriteSheetExporter {
+    config: SpriteSheetExportConfig,
+    frames: Option<Vec<RgbaImage>>,
+    /// Frames announced by [`with_frame_count`](Self::with_frame_count)
+    frame_count: Option<usize>,
+    writer: Option<SpriteSheetWriter>,
+}
+
+impl SpriteSheetExporter {
+    /// Create a new sprite sheet exporter
+    pub fn new(config: SpriteSheetExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            frames: None,
+            frame_count: None,
+            writer: None,
+        })
+    }
+
+    /// Place frames into the atlas as they arrive instead of holding them
+    /// until [`finish`](Self::finish); exactly `frame_count` must follow
+    pub fn with_frame_count(mut self, frame_count: usize) -> Self {
+        self.frame_count = Some(frame_count);
+        self
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        self.frames = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Add a frame to the sheet
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        let frames = self.frames.as_mut()
+            .ok_or_else(|| ExportError::SpriteEncode("Exporter not started".to_string()))?;
+        let Some(frame_count) = self.frame_count else {
+            frames.push(image);
+            return Ok(());
+        };
+        if self.writer.is_none() {
+            self.writer = Some(SpriteSheetWriter::new(&image, frame_count, &self.config)?);
+        }
+        match self.writer.as_mut() {
+            Some(writer) => writer.add_frame(image),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish the export and return the path of the JSON descriptor
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        if let Some(writer) = self.writer.take() {
+            return writer.finish();
+        }
+        let frames = self.frames.take().unwrap_or_default();
+        let count = frames.len();
+        write_sprite_sheets(frames.into_iter().map(Ok), count, &self.config, None)
+    }
+
+    /// Stop without finishing and remove the sheets written so far
+    pub(crate) fn abort(mut self) {
+        if let Some(writer) = self.writer.take() {
+            writer.abort();
+        }
+    }
+
+    /// Export PNG files to a sprite sheet
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: SpriteSheetExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        // Frames are placed as they are decoded, so only one atlas is in memory
+        let frames = png_paths
+            .iter()
+            .map(|path| Ok(image::open(path)?.to_rgba8()));
+        write_sprite_sheets(frames, png_paths.len(), &config, progress.as_ref())
+    }
+}
+
+fn write_sprite_sheets(
+    mut frames: impl Iterator<Item = ExportResult<RgbaImage>>,
+    frame_count: usize,
+    config: &SpriteSheetExportConfig,
+    progress: Option<&ProgressCallback>,
+) -> ExportResult<PathBuf> {
+    let first = frames.next().ok_or(ExportError::NoFrames)??;
+    let mut writer = SpriteSheetWriter::new(&first, frame_count, config)?;
+
+    for (i, frame) in std::iter::once(Ok(first)).chain(frames).enumerate() {
+        writer.add_frame(frame?)?;
+
+        if let Some(cb) = progress {
+            cb((i + 1) as f32 / frame_count.max(1) as f32);
+        }
+    }
+
+    writer.finish()
+}
+
+/// Places frames into the sheets one at a time, saving each sheet once it
+/// is full
+struct SpriteSheetWriter {
+    config: SpriteSheetExportConfig,
+    width: u32,
+    height: u32,
+    layout: SpriteLayout,
+    sheets: Vec<SheetInfo>,
+    frames: Vec<FrameInfo>,
+    /// Sheet being filled
+    canvas: Option<(usize, RgbaImage)>,
+}
+
+impl SpriteSheetWriter {
+    /// Writer for `frame_count` frames, sized after `first`
+    fn new(first: &RgbaImage, frame_count: usize, config: &SpriteSheetExportConfig) -> ExportResult<Self> {
+        let (width, height) = fit_dimensions(first.width(), first.height(), config.width, config.height);
+        let layout = SpriteLayout::new(width, height, frame_count, config)?;
+        Ok(Self {
+            config: config.clone(),
+            width,
+            height,
+            sheets: Vec::with_capacity(layout.sheet_count()),
+            frames: Vec::with_capacity(frame_count),
+            layout,
+            canvas: None,
+        })
+    }
+
+    fn add_frame(&mut self, frame: RgbaImage) -> ExportResult<()> {
+        let i = self.frames.len();
+        if i >= self.layout.frame_count {
+            return Err(ExportError::SpriteEncode(format!(
+                "more than the {} announced frames",
+                self.layout.frame_count
+            )));
+        }
+        let frame = resize_to(frame, self.width, self.height);
+        let (sheet, x, y) = self.layout.position(i);
+
+        if self.canvas.as_ref().is_none_or(|(current, _)| *current != sheet) {
+            self.save_canvas()?;
+            let (sheet_width, sheet_height) = self.layout.sheet_size(sheet);
+            self.canvas = Some((sheet, RgbaImage::new(sheet_width, sheet_height)));
+        }
+        if let Some((_, image)) = self.canvas.as_mut() {
+            imageops::replace(image, &frame, x as i64, y as i64);
+        }
+
+        // Round cumulative times so the delays never drift
+        let config = &self.config;
+        let millis = |index| (frame_time(&config.timestamps, config.fps, index) * 1000.0).round() as u32;
+        self.frames.push(FrameInfo {
+            sheet,
+            x,
+            y,
+            width: self.width,
+            height: self.height,
+            delay_ms: millis(i + 1).saturating_sub(millis(i)),
+        });
+        Ok(())
+    }
+
+    /// Save the sheet being filled, if any
+    fn save_canvas(&mut self) -> ExportResult<()> {
+        if let Some((done, image)) = self.canvas.take() {
+            let sheet_count = self.layout.sheet_count();
+            self.sheets.push(save_sheet(&image, &self.config.output_path, done, sheet_count)?);
+        }
+        Ok(())
+    }
+
+    /// Save the last sheet, the JSON descriptor and the CSS, returning the
+    /// descriptor's path
+    fn finish(mut self) -> ExportResult<PathBuf> {
+        let (arrived, expected) = (self.frames.len(), self.layout.frame_count);
+        if arrived != expected {
+            self.abort();
+            return Err(ExportError::SpriteEncode(format!(
+                "{} of {} announced frames arrived",
+                arrived, expected
+            )));
+        }
+        self.save_canvas()?;
+
+        let descriptor = SpriteSheetDescriptor {
+            frame_width: self.width,
+            frame_height: self.height,
+            fps: self.config.fps,
+            duration_ms: self.frames.iter().map(|f| f.delay_ms as u64).sum(),
+            sheets: self.sheets,
+            frames: self.frames,
+        };
+
+        let output_path = &self.config.output_path;
+        let json_path = output_path.with_extension("json");
+        let json = serde_json::to_string_pretty(&descriptor)
+            .map_err(|e| ExportError::SpriteEncode(e.to_string()))?;
+        fs::write(&json_path, json)?;
+        fs::write(output_path.with_extension("css"), css(&descriptor, output_path))?;
+
+        Ok(json_path)
+    }
+
+    /// Remove the sheets saved so far
+    fn abort(self) {
+        for sheet in &self.sheets {
+            let _ = fs::remove_file(self.config.output_path.with_file_name(&sheet.image));
+        }
+    }
+}
+
+/// Save one atlas; multiple sheets get a `_N` suffix
+fn save_sheet(image: &RgbaImage, output_path: &Path, sheet: usize, sheet_count: usize) -> ExportResult<SheetInfo> {
+    let path = if sheet_count == 1 {
+        output_path.with_extension("png")
+    } else {
+        let stem = output_path.file_stem().and_then(|s| s.to_str()).unwrap_or("sprite");
+        output_path.with_file_name(format!("{}_{}.png", stem, sheet))
+    };
+    image.save_with_format(&path, image::ImageFormat::Png)?;
+
+    Ok(SheetInfo {
+        image: path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+/// Build a CSS class that plays the animation with `steps()` timing
+fn css(descriptor: &SpriteSheetDescriptor, output_path: &Path) -> String {
+    let name = css_identifier(output_path);
+    let multi_sheet = descriptor.sheets.len() > 1;
+    let mut css = String::new();
+
+    let _ = writeln!(css, "/* Generated by WinGIF */");
+    let _ = writeln!(css, ".{} {{", name);
+    let _ = writeln!(css, "  width: {}px;", descriptor.frame_width);
+    let _ = writeln!(css, "  height: {}px;", descriptor.frame_height);
+    if let Some(sheet) = descriptor.sheets.first() {
+        let _ = writeln!(css, "  background: url(\"{}\") no-repeat;", sheet.image);
+    }
+    let _ = writeln!(
+        css,
+        "  animation: {} {}ms steps(1, end) infinite;",
+        name, descriptor.duration_ms
+    );
+    let _ = writeln!(css, "}}");
+    let _ = writeln!(css);
+
+    // One keyframe per frame; steps(1, end) holds each position until the next
+    let _ = writeln!(css, "@keyframes {} {{", name);
+    let total = descriptor.duration_ms.max(1) as f64;
+    let mut elapsed = 0u64;
+    let mut last = String::new();
+    for frame in &descriptor.frames {
+        let mut rule = String::new();
+        if multi_sheet {
+            let _ = write!(rule, "background-image: url(\"{}\"); ", descriptor.sheets[frame.sheet].image);
+        }
+        let _ = write!(rule, "background-position: -{}px -{}px;", frame.x, frame.y);
+
+        let _ = writeln!(css, "  {:.4}% {{ {} }}", elapsed as f64 * 100.0 / total, rule);
+        elapsed += frame.delay_ms as u64;
+        last = rule;
+    }
+    let _ = writeln!(css, "  100% {{ {} }}", last);
+    let _ = writeln!(css, "}}");
+
+    css
+}
+
+/// Turn the file stem into a valid CSS class name
+fn css_identifier(output_path: &Path) -> String {
+    let stem = output_path.file_stem().and_then(|s| s.to_str()).unwrap_or("sprite");
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("sprite-{}", name)
+    } else {
+        name
+    }
+}
+
+/// Sprite sheet entry in the format registry
+pub(crate) struct SpriteSheetFormat;
+
+impl Exporter for SpriteSheetFormat {
+    fn id(&self) -> &'static str {
+        "sprite-sheet"
+    }
+
+    fn name(&self) -> &'static str {
+        "Sprite sheet"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["png"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            alpha: true,
+            variable_delays: true,
+            max_colors: None,
+            lossy: false,
+            lossless: true,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = SpriteSheetExportConfig::default();
+        let mut options = vec![
+            OptionSpec::int("columns", "Columns (0 = square)", 0, 1024, 0),
+            OptionSpec::int("padding", "Padding", 0, 256, defaults.padding as i64),
+            OptionSpec::int(
+                "max_texture_size",
+                "Max texture size",
+                64,
+                16384,
+                defaults.max_texture_size as i64,
+            ),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let defaults = SpriteSheetExportConfig::default();
+        let options = &config.options;
+        let sprite_config = SpriteSheetExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            timestamps: config.frame_timestamps(png_paths.len()),
+            columns: options.dimension("columns"),
+            padding: options.u32_or("padding", defaults.padding),
+            max_texture_size: options.u32_or("max_texture_size", defaults.max_texture_size),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+        };
+        SpriteSheetExporter::export_from_pngs(png_paths, sprite_config, progress)
+    }
+}
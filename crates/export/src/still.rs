@@ -0,0 +1,260 @@
+//! Single frame export
+//!
+//! Writes one frame of a recording as a PNG, JPEG or WebP still image.
+
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, resize_to, save_jpeg, save_webp};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use color_quant::NeuQuant;
+use image::RgbaImage;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Output format of a still image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StillImageFormat {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl StillImageFormat {
+    /// Guess the format from the extension of `path`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "webp" => Some(Self::WebP),
+            _ => None,
+        }
+    }
+}
+
+/// Still image export configuration
+#[derive(Debug, Clone)]
+pub struct StillExportConfig {
+    pub output_path: PathBuf,
+    /// Zero-based index of the frame to export
+    pub frame: usize,
+    pub format: StillImageFormat,
+    /// JPEG and WebP quality, 0-100
+    pub quality: u8,
+    /// PNG deflate level, 0 (stored) to 9 (smallest)
+    pub png_compression: u8,
+    /// Reduce to at most this many colors (2-256); PNGs are then written indexed
+    pub palette_colors: Option<u16>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Default for StillExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            frame: 0,
+            format: StillImageFormat::Png,
+            quality: 90,
+            png_compression: 6,
+            palette_colors: None,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+/// Still image exporter
+pub struct StillExporter;
+
+impl StillExporter {
+    /// Write `image` according to `config`, ignoring `config.frame`
+    pub fn export_image(image: RgbaImage, config: &StillExportConfig) -> ExportResult<PathBuf> {
+        let (width, height) =
+            fit_dimensions(image.width(), image.height(), config.width, config.height);
+        let mut image = resize_to(image, width, height);
+
+        let palette = config
+            .palette_colors
+            .map(|colors| NeuQuant::new(10, colors.clamp(2, 256) as usize, image.as_raw()));
+
+        match config.format {
+            StillImageFormat::Png => match palette {
+                Some(quant) => write_indexed_png(&image, &quant, config)?,
+                None => write_png(&image, config)?,
+            },
+            StillImageFormat::Jpeg => {
+                if let Some(quant) = &palette {
+                    reduce_colors(&mut image, quant);
+                }
+                save_jpeg(image, &config.output_path, config.quality)?;
+            }
+            StillImageFormat::WebP => {
+                if let Some(quant) = &palette {
+                    reduce_colors(&mut image, quant);
+                }
+                save_webp(&image, &config.output_path, config.quality)?;
+            }
+        }
+
+        Ok(config.output_path.clone())
+    }
+
+    /// Export frame `config.frame` of a recorded PNG sequence
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: StillExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let path = png_paths.get(config.frame).ok_or_else(|| {
+            ExportError::StillEncode(format!(
+                "Frame {} out of range, recording has {} frames",
+                config.frame,
+                png_paths.len()
+            ))
+        })?;
+        let image = image::open(path)?.to_rgba8();
+        let output = Self::export_image(image, &config)?;
+
+        if let Some(cb) = progress {
+            cb(1.0);
+        }
+        Ok(output)
+    }
+}
+
+fn png_encoder<'a>(
+    config: &StillExportConfig,
+    width: u32,
+    height: u32,
+) -> ExportResult<png::Encoder<'a, BufWriter<File>>> {
+    let file = BufWriter::new(File::create(&config.output_path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_deflate_compression(match config.png_compression {
+        0 => png::DeflateCompression::NoCompression,
+        level => png::DeflateCompression::Level(level.min(9)),
+    });
+    Ok(encoder)
+}
+
+fn write_png(image: &RgbaImage, config: &StillExportConfig) -> ExportResult<()> {
+    let mut encoder = png_encoder(config, image.width(), image.height())?;
+    encoder.set_color(png::ColorType::Rgba);
+    encode_png(encoder, image.as_raw())
+}
+
+/// Write a palette PNG, with a tRNS chunk only if the image is translucent
+fn write_indexed_png(
+    image: &RgbaImage,
+    quant: &NeuQuant,
+    config: &StillExportConfig,
+) -> ExportResult<()> {
+    let rgba = quant.color_map_rgba();
+    let rgb: Vec<u8> = rgba.chunks_exact(4).flat_map(|c| [c[0], c[1], c[2]]).collect();
+    let alpha: Vec<u8> = rgba.chunks_exact(4).map(|c| c[3]).collect();
+    let indices: Vec<u8> = image
+        .as_raw()
+        .chunks_exact(4)
+        .map(|pixel| quant.index_of(pixel) as u8)
+        .collect();
+
+    let mut encoder = png_encoder(config, image.width(), image.height())?;
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_palette(rgb);
+    // NeuQuant averages alpha too, so opaque images can end up with 254s
+    if image.pixels().any(|p| p[3] < 255) {
+        encoder.set_trns(alpha);
+    }
+    encode_png(encoder, &indices)
+}
+
+fn encode_png(encoder: png::Encoder<'_, BufWriter<File>>, data: &[u8]) -> ExportResult<()> {
+    let still_error = |e: png::EncodingError| ExportError::StillEncode(e.to_string());
+    let mut writer = encoder.write_header().map_err(still_error)?;
+    writer.write_image_data(data).map_err(still_error)?;
+    writer.finish().map_err(still_error)?;
+    Ok(())
+}
+
+/// Snap every pixel to its nearest palette color
+fn reduce_colors(image: &mut RgbaImage, quant: &NeuQuant) {
+    for pixel in image.pixels_mut() {
+        quant.map_pixel(&mut pixel.0);
+    }
+}
+
+/// Still image entry in the format registry
+///
+/// The image format follows the extension of the output path.
+pub(crate) struct StillFormat;
+
+impl Exporter for StillFormat {
+    fn id(&self) -> &'static str {
+        "still"
+    }
+
+    fn name(&self) -> &'static str {
+        "Still image (PNG/JPEG/WebP)"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["png", "jpg", "jpeg", "webp"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: false,
+            alpha: true,
+            variable_delays: false,
+            max_colors: None,
+            lossy: true,
+            lossless: true,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = StillExportConfig::default();
+        let mut options = vec![
+            OptionSpec::int("frame", "Frame index", 0, 1_000_000, 0),
+            OptionSpec::int("quality", "JPEG/WebP quality", 0, 100, defaults.quality as i64),
+            OptionSpec::int(
+                "png_compression",
+                "PNG compression level",
+                0,
+                9,
+                defaults.png_compression as i64,
+            ),
+            OptionSpec::int("palette_colors", "Palette colors (0 = full color)", 0, 256, 0),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let defaults = StillExportConfig::default();
+        let options = &config.options;
+        let palette_colors = options.int_or("palette_colors", 0);
+        let still_config = StillExportConfig {
+            output_path: config.output_path.clone(),
+            frame: options.int_or("frame", 0).max(0) as usize,
+            format: StillImageFormat::from_path(&config.output_path)
+                .unwrap_or(StillImageFormat::Png),
+            quality: options.u8_or("quality", defaults.quality),
+            png_compression: options.u8_or("png_compression", defaults.png_compression),
+            palette_colors: (palette_colors > 0).then(|| palette_colors.min(256) as u16),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+        };
+        StillExporter::export_from_pngs(png_paths, still_config, progress)
+    }
+}
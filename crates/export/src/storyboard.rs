@@ -0,0 +1,334 @@
+//! Storyboard (contact sheet) export
+//!
+//! Lays a selection of frames out in a grid on a single PNG, each captioned
+//! with its frame index and timestamp.
+
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, resize_to};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use image::{imageops, Rgba, RgbaImage};
+use std::path::PathBuf;
+
+/// Which frames end up on the storyboard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoryboardSelection {
+    /// Every Nth frame, starting with the first
+    Every(usize),
+    /// Explicit zero-based frame indices; out-of-range entries are skipped
+    Frames(Vec<usize>),
+}
+
+/// Storyboard export configuration
+#[derive(Debug, Clone)]
+pub struct StoryboardExportConfig {
+    pub output_path: PathBuf,
+    pub fps: u8,
+    pub selection: StoryboardSelection,
+    pub columns: u32,
+    /// Maximum thumbnail width; frames are never scaled up
+    pub cell_width: Option<u32>,
+    /// Gap around and between cells in pixels
+    pub padding: u32,
+    pub background: [u8; 4],
+    /// Draw "#index mm:ss.cc" under every thumbnail
+    pub captions: bool,
+}
+
+impl Default for StoryboardExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            selection: StoryboardSelection::Every(10),
+            columns: 4,
+            cell_width: Some(320),
+            padding: 8,
+            background: [32, 32, 32, 255],
+            captions: true,
+        }
+    }
+}
+
+impl StoryboardSelection {
+    fn contains(&self, index: usize) -> bool {
+        match self {
+            Self::Every(step) => index.is_multiple_of((*step).max(1)),
+            Self::Frames(frames) => frames.contains(&index),
+        }
+    }
+}
+
+/// Glyph height of the built-in font in font pixels
+const GLYPH_HEIGHT: u32 = 7;
+const GLYPH_WIDTH: u32 = 5;
+/// Each font pixel becomes a square of this size, unless captions would
+/// not fit the cell at that size
+const CAPTION_SCALE: u32 = 2;
+
+/// Storyboard exporter
+pub struct StoryboardExporter {
+    config: StoryboardExportConfig,
+    /// Selected thumbnails with their frame index
+    cells: Option<Vec<(usize, RgbaImage)>>,
+    frame_index: usize,
+}
+
+impl StoryboardExporter {
+    /// Create a new storyboard exporter
+    pub fn new(config: StoryboardExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            cells: None,
+            frame_index: 0,
+        })
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        self.cells = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Offer the next frame; only selected frames are kept
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        let cells = self.cells.as_mut()
+            .ok_or_else(|| ExportError::StoryboardEncode("Exporter not started".to_string()))?;
+
+        if self.config.selection.contains(self.frame_index) {
+            let (width, height) =
+                fit_dimensions(image.width(), image.height(), self.config.cell_width, None);
+            cells.push((self.frame_index, resize_to(image, width, height)));
+        }
+        self.frame_index += 1;
+        Ok(())
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        let cells = self.cells.take().unwrap_or_default();
+        if cells.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let sheet = self.render(&cells);
+        sheet.save_with_format(&self.config.output_path, image::ImageFormat::Png)?;
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to a storyboard, decoding only the selected frames
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: StoryboardExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let total = png_paths.len();
+        let mut exporter = Self::new(config)?;
+        exporter.start()?;
+
+        for (i, path) in png_paths.iter().enumerate() {
+            if exporter.config.selection.contains(i) {
+                let img = image::open(path)?.to_rgba8();
+                exporter.add_frame(img)?;
+            } else {
+                exporter.frame_index += 1;
+            }
+
+            if let Some(ref cb) = progress {
+                cb((i + 1) as f32 / total as f32);
+            }
+        }
+
+        exporter.finish()
+    }
+
+    fn render(&self, cells: &[(usize, RgbaImage)]) -> RgbaImage {
+        let padding = self.config.padding;
+        let columns = self.config.columns.clamp(1, cells.len() as u32);
+        let rows = (cells.len() as u32).div_ceil(columns);
+
+        // Frames of one recording share a size, but don't rely on it
+        let cell_width = cells.iter().map(|(_, img)| img.width()).max().unwrap_or(1);
+        let thumb_height = cells.iter().map(|(_, img)| img.height()).max().unwrap_or(1);
+        let fps = self.config.fps.max(1) as f64;
+        let captions: Vec<String> = cells
+            .iter()
+            .map(|(index, _)| format!("#{} {}", index, format_timestamp(*index as f64 / fps)))
+            .collect();
+        let longest = captions.iter().map(|c| c.chars().count() as u32).max().unwrap_or(0);
+        let scale = if text_width(longest, CAPTION_SCALE) <= cell_width {
+            CAPTION_SCALE
+        } else {
+            1
+        };
+        let caption_height = if self.config.captions {
+            GLYPH_HEIGHT * scale + padding
+        } else {
+            0
+        };
+        let cell_height = thumb_height + caption_height;
+
+        let width = columns * cell_width + (columns + 1) * padding;
+        let height = rows * cell_height + (rows + 1) * padding;
+        let mut sheet = RgbaImage::from_pixel(width, height, Rgba(self.config.background));
+        let text_color = caption_color(self.config.background);
+
+        for (n, ((_, thumb), caption)) in cells.iter().zip(&captions).enumerate() {
+            let x = padding + (n as u32 % columns) * (cell_width + padding);
+            let y = padding + (n as u32 / columns) * (cell_height + padding);
+            imageops::overlay(&mut sheet, thumb, x as i64, y as i64);
+
+            if self.config.captions {
+                let text = Text {
+                    x,
+                    y: y + thumb_height + padding / 2,
+                    max_width: cell_width,
+                    scale,
+                };
+                draw_text(&mut sheet, caption, &text, text_color);
+            }
+        }
+
+        sheet
+    }
+}
+
+/// `mm:ss.cc`
+fn format_timestamp(seconds: f64) -> String {
+    let centis = (seconds * 100.0).round() as u64;
+    format!("{:02}:{:02}.{:02}", centis / 6000, centis / 100 % 60, centis % 100)
+}
+
+/// Light text on dark backgrounds and vice versa
+fn caption_color([r, g, b, _]: [u8; 4]) -> Rgba<u8> {
+    let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+    if luma < 128.0 {
+        Rgba([230, 230, 230, 255])
+    } else {
+        Rgba([20, 20, 20, 255])
+    }
+}
+
+/// Width in pixels of `chars` glyphs at `scale`
+fn text_width(chars: u32, scale: u32) -> u32 {
+    (chars * (GLYPH_WIDTH + 1)).saturating_sub(1) * scale
+}
+
+/// Placement of a caption
+struct Text {
+    x: u32,
+    y: u32,
+    /// Glyph pixels past this width are clipped
+    max_width: u32,
+    scale: u32,
+}
+
+/// Draw `caption` with the built-in 5x7 font
+fn draw_text(image: &mut RgbaImage, caption: &str, text: &Text, color: Rgba<u8>) {
+    let right = (text.x + text.max_width).min(image.width());
+    let advance = (GLYPH_WIDTH + 1) * text.scale;
+
+    for (i, c) in caption.chars().enumerate() {
+        let origin_x = text.x + i as u32 * advance;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..text.scale {
+                    for dx in 0..text.scale {
+                        let px = origin_x + col * text.scale + dx;
+                        let py = text.y + row as u32 * text.scale + dy;
+                        if px < right && py < image.height() {
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rows of a 5x7 glyph, most significant of the low five bits on the left
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        _ => [0x00; 7],
+    }
+}
+
+/// Storyboard entry in the format registry
+pub(crate) struct StoryboardFormat;
+
+impl Exporter for StoryboardFormat {
+    fn id(&self) -> &'static str {
+        "storyboard"
+    }
+
+    fn name(&self) -> &'static str {
+        "Storyboard"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["png"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: false,
+            alpha: false,
+            variable_delays: false,
+            max_colors: None,
+            lossy: false,
+            lossless: true,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = StoryboardExportConfig::default();
+        vec![
+            OptionSpec::int("every", "Every Nth frame", 1, 10_000, 10),
+            OptionSpec::int("columns", "Columns", 1, 64, defaults.columns as i64),
+            OptionSpec::int("cell_width", "Thumbnail width (0 = original)", 0, 4096, 320),
+            OptionSpec::int("padding", "Padding", 0, 256, defaults.padding as i64),
+            OptionSpec::bool("captions", "Captions", defaults.captions),
+        ]
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let defaults = StoryboardExportConfig::default();
+        let options = &config.options;
+        let every = options.int_or("every", 10).max(1) as usize;
+        let storyboard_config = StoryboardExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            selection: StoryboardSelection::Every(every),
+            columns: options.u32_or("columns", defaults.columns),
+            cell_width: options.dimension("cell_width"),
+            padding: options.u32_or("padding", defaults.padding),
+            background: defaults.background,
+            captions: options.bool_or("captions", defaults.captions),
+        };
+        StoryboardExporter::export_from_pngs(png_paths, storyboard_config, progress)
+    }
+}
@@ -0,0 +1,27 @@
+//! Helpers shared by the unit tests
+
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// Write `count` frames from [`frame`] into `dir` as a recording would
+pub(crate) fn write_frames(dir: &Path, count: usize) -> Vec<PathBuf> {
+    (0..count)
+        .map(|i| {
+            let path = dir.join(format!("frame_{:05}.png", i));
+            frame(i).save(&path).unwrap();
+            path
+        })
+        .collect()
+}
+
+/// A gradient with a white square moving right, different for every `index`
+pub(crate) fn frame(index: usize) -> RgbaImage {
+    RgbaImage::from_fn(32, 24, |x, y| {
+        let left = index as u32 * 3 % 24;
+        if (left..left + 8).contains(&x) && (8..16).contains(&y) {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([(x * 8) as u8, (y * 10) as u8, (index * 40) as u8, 255])
+        }
+    })
+}
@@ -0,0 +1,70 @@
+//! Shared frame helpers for exporters
+
+use crate::ExportResult;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, RgbaImage};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+/// Fit `width` x `height` inside the optional bounds, keeping the aspect ratio.
+///
+/// Images are never scaled up; a bound of `None` leaves that axis unconstrained.
+pub(crate) fn fit_dimensions(
+    width: u32,
+    height: u32,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+) -> (u32, u32) {
+    let mut scale = 1.0f64;
+    if let Some(max_w) = max_width.filter(|&w| w > 0) {
+        scale = scale.min(max_w as f64 / width as f64);
+    }
+    if let Some(max_h) = max_height.filter(|&h| h > 0) {
+        scale = scale.min(max_h as f64 / height as f64);
+    }
+
+    let w = ((width as f64 * scale).round() as u32).max(1);
+    let h = ((height as f64 * scale).round() as u32).max(1);
+    (w, h)
+}
+
+/// Resize an image to exactly `width` x `height` unless it already matches
+pub(crate) fn resize_to(img: RgbaImage, width: u32, height: u32) -> RgbaImage {
+    if img.width() == width && img.height() == height {
+        img
+    } else {
+        imageops::resize(&img, width, height, FilterType::Lanczos3)
+    }
+}
+
+/// Save as JPEG at `quality` (1-100), dropping the alpha channel
+pub(crate) fn save_jpeg(img: RgbaImage, path: &Path, quality: u8) -> ExportResult<()> {
+    let rgb = DynamicImage::ImageRgba8(img).to_rgb8();
+    let file = BufWriter::new(File::create(path)?);
+    JpegEncoder::new_with_quality(file, quality.clamp(1, 100)).encode_image(&rgb)?;
+    Ok(())
+}
+
+/// Save as lossy WebP at `quality` (0-100)
+pub(crate) fn save_webp(img: &RgbaImage, path: &Path, quality: u8) -> ExportResult<()> {
+    let encoder = webp::Encoder::from_rgba(img.as_raw(), img.width(), img.height());
+    let data = encoder.encode(quality.min(100) as f32);
+    fs::write(path, &*data)?;
+    Ok(())
+}
+
+/// Presentation time of frame `index` in seconds
+///
+/// Frames past the end of `timestamps`, or all frames when it is empty, are
+/// spaced `1 / fps` apart.
+pub(crate) fn frame_time(timestamps: &[Duration], fps: u8, index: usize) -> f64 {
+    let interval = 1.0 / fps.max(1) as f64;
+    match (timestamps.get(index), timestamps.last()) {
+        (Some(t), _) => t.as_secs_f64(),
+        (None, Some(last)) => last.as_secs_f64() + (index + 1 - timestamps.len()) as f64 * interval,
+        (None, None) => index as f64 * interval,
+    }
+}
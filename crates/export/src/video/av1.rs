@@ -0,0 +1,438 @@
+//! AV1 video export using rav1e
+
+use super::{VideoCodec, VideoContainer, VideoPreset, VideoSample, VideoTrack};
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, frame_time, resize_to};
+use crate::yuv::{alpha_plane, Yuv420};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use image::RgbaImage;
+use rav1e::prelude::*;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// rav1e rejects frames smaller than this in either dimension
+const MIN_DIMENSION: u32 = 16;
+
+const OBU_SEQUENCE_HEADER: u8 = 1;
+const OBU_TEMPORAL_DELIMITER: u8 = 2;
+
+/// AV1 export configuration
+#[derive(Debug, Clone)]
+pub struct Av1ExportConfig {
+    pub output_path: PathBuf,
+    pub fps: u8,
+    /// Capture time of each frame, relative to the first; frames past its
+    /// end, or all frames when empty, follow `fps`
+    pub timestamps: Vec<Duration>,
+    pub container: VideoContainer,
+    pub preset: VideoPreset,
+    /// Target bitrate; `None` uses the preset's constant quantizer
+    pub bitrate_kbps: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Default for Av1ExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            timestamps: Vec::new(),
+            container: VideoContainer::Mp4,
+            preset: VideoPreset::Balanced,
+            bitrate_kbps: None,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+impl VideoPreset {
+    /// rav1e speed level (0 = slowest, 10 = fastest)
+    fn av1_speed(self) -> u8 {
+        match self {
+            VideoPreset::Fast => 10,
+            VideoPreset::Balanced => 8,
+            VideoPreset::Quality => 5,
+        }
+    }
+
+    /// rav1e base quantizer (0-255, lower is better)
+    fn av1_quantizer(self) -> usize {
+        match self {
+            VideoPreset::Fast => 140,
+            VideoPreset::Balanced => 110,
+            VideoPreset::Quality => 80,
+        }
+    }
+}
+
+/// rav1e settings shared by the video and AVIF exporters
+pub(super) struct Av1Settings {
+    pub fps: u8,
+    /// Capture time of each frame; empty follows `fps`
+    pub timestamps: Vec<Duration>,
+    /// 0 = slowest, 10 = fastest
+    pub speed: u8,
+    /// 0-255, lower is better
+    pub quantizer: usize,
+    pub bitrate_kbps: Option<u32>,
+    /// Encode the alpha channel as a monochrome full-range stream
+    pub alpha: bool,
+}
+
+impl From<&Av1ExportConfig> for Av1Settings {
+    fn from(config: &Av1ExportConfig) -> Self {
+        Self {
+            fps: config.fps,
+            timestamps: config.timestamps.clone(),
+            speed: config.preset.av1_speed(),
+            quantizer: config.preset.av1_quantizer(),
+            bitrate_kbps: config.bitrate_kbps,
+            alpha: false,
+        }
+    }
+}
+
+/// Streaming rav1e wrapper that collects packets for the muxer
+pub(super) struct Av1Encoder {
+    ctx: Context<u8>,
+    /// Output size before padding
+    width: u32,
+    height: u32,
+    /// Encoded size
+    coded_width: u32,
+    coded_height: u32,
+    fps: u8,
+    timestamps: Vec<Duration>,
+    alpha: bool,
+    sequence_header: Option<Vec<u8>>,
+    samples: Vec<VideoSample>,
+    pub progress: Option<(ProgressCallback, usize)>,
+}
+
+impl Av1Encoder {
+    pub fn new(width: u32, height: u32, settings: &Av1Settings) -> ExportResult<Self> {
+        let coded_width = width.max(MIN_DIMENSION);
+        let coded_height = height.max(MIN_DIMENSION);
+        let fps = settings.fps.max(1);
+
+        let mut enc = EncoderConfig::with_speed_preset(settings.speed.min(10));
+        enc.width = coded_width as usize;
+        enc.height = coded_height as usize;
+        enc.time_base = Rational::new(1, fps as u64);
+        if settings.alpha {
+            enc.chroma_sampling = ChromaSampling::Cs400;
+            enc.pixel_range = PixelRange::Full;
+        } else {
+            enc.chroma_sampling = ChromaSampling::Cs420;
+            enc.pixel_range = PixelRange::Limited;
+            enc.color_description = Some(ColorDescription {
+                color_primaries: ColorPrimaries::BT709,
+                transfer_characteristics: TransferCharacteristics::BT709,
+                matrix_coefficients: MatrixCoefficients::BT709,
+            });
+        }
+        // A keyframe every 10 seconds keeps long recordings seekable
+        enc.max_key_frame_interval = fps as u64 * 10;
+        enc.quantizer = settings.quantizer.min(255);
+        if let Some(kbps) = settings.bitrate_kbps {
+            enc.bitrate = (kbps as i32).saturating_mul(1000);
+        }
+
+        let ctx = Config::new()
+            .with_encoder_config(enc)
+            .new_context()
+            .map_err(|e| ExportError::VideoEncode(e.to_string()))?;
+
+        Ok(Self {
+            ctx,
+            width,
+            height,
+            coded_width,
+            coded_height,
+            fps,
+            timestamps: settings.timestamps.clone(),
+            alpha: settings.alpha,
+            sequence_header: None,
+            samples: Vec::new(),
+            progress: None,
+        })
+    }
+
+    pub fn send_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        let image = resize_to(image, self.width, self.height);
+        let coded_width = self.coded_width as usize;
+        let coded_height = self.coded_height as usize;
+
+        let mut frame = self.ctx.new_frame();
+        if self.alpha {
+            let plane = alpha_plane(&image, coded_width, coded_height);
+            frame.planes[0].copy_from_raw_u8(&plane, coded_width, 1);
+        } else {
+            let yuv = Yuv420::from_rgba(&image, coded_width, coded_height);
+            frame.planes[0].copy_from_raw_u8(&yuv.y, yuv.width, 1);
+            frame.planes[1].copy_from_raw_u8(&yuv.u, yuv.chroma_width(), 1);
+            frame.planes[2].copy_from_raw_u8(&yuv.v, yuv.chroma_width(), 1);
+        }
+
+        self.ctx
+            .send_frame(frame)
+            .map_err(|e| ExportError::VideoEncode(e.to_string()))?;
+        self.receive_packets()
+    }
+
+    fn receive_packets(&mut self) -> ExportResult<()> {
+        loop {
+            match self.ctx.receive_packet() {
+                Ok(packet) => self.push_packet(packet),
+                Err(EncoderStatus::Encoded) => {}
+                Err(EncoderStatus::NeedMoreData) | Err(EncoderStatus::LimitReached) => break,
+                Err(e) => return Err(ExportError::VideoEncode(e.to_string())),
+            }
+        }
+        Ok(())
+    }
+
+    fn push_packet(&mut self, packet: Packet<u8>) {
+        if self.sequence_header.is_none() {
+            self.sequence_header = find_obu(&packet.data, OBU_SEQUENCE_HEADER).map(<[u8]>::to_vec);
+        }
+
+        self.samples.push(VideoSample {
+            data: strip_temporal_delimiters(&packet.data),
+            timestamp: self.frame_time(packet.input_frameno as usize),
+            keyframe: packet.frame_type == FrameType::KEY,
+        });
+
+        if let Some((ref cb, total)) = self.progress {
+            cb(self.samples.len() as f32 / total.max(1) as f32);
+        }
+    }
+
+    /// Presentation time of frame `index` in seconds
+    fn frame_time(&self, index: usize) -> f64 {
+        frame_time(&self.timestamps, self.fps, index)
+    }
+
+    pub fn finish(mut self) -> ExportResult<VideoTrack> {
+        self.ctx.flush();
+        self.receive_packets()?;
+
+        if self.samples.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        // av1C record followed by the sequence header as configOBUs
+        let mut config = self.ctx.container_sequence_header();
+        if let Some(header) = self.sequence_header.take() {
+            config.extend_from_slice(&header);
+        }
+
+        let end = self.frame_time(self.samples.len());
+        let last = self.samples.last().map_or(0.0, |s| s.timestamp);
+        Ok(VideoTrack {
+            codec: VideoCodec::Av1 { config },
+            width: self.coded_width,
+            height: self.coded_height,
+            samples: self.samples,
+            frame_duration: (end - last).max(0.0),
+        })
+    }
+}
+
+/// Iterate over `(type, whole OBU)` pairs in a temporal unit
+fn obus(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        if pos >= data.len() {
+            return None;
+        }
+        let start = pos;
+        let header = data[pos];
+        let obu_type = (header >> 3) & 0x0F;
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+        pos += 1 + has_extension as usize;
+
+        let end = if has_size {
+            let mut size = 0usize;
+            for i in 0..8 {
+                let byte = *data.get(pos)?;
+                pos += 1;
+                size |= ((byte & 0x7F) as usize) << (7 * i);
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            (pos + size).min(data.len())
+        } else {
+            data.len()
+        };
+
+        pos = end;
+        Some((obu_type, &data[start..end]))
+    })
+}
+
+fn find_obu(data: &[u8], obu_type: u8) -> Option<&[u8]> {
+    obus(data).find(|(t, _)| *t == obu_type).map(|(_, obu)| obu)
+}
+
+/// Containers carry temporal units without temporal delimiter OBUs
+fn strip_temporal_delimiters(data: &[u8]) -> Vec<u8> {
+    obus(data)
+        .filter(|(t, _)| *t != OBU_TEMPORAL_DELIMITER)
+        .flat_map(|(_, obu)| obu.iter().copied())
+        .collect()
+}
+
+/// AV1 video exporter
+pub struct Av1Exporter {
+    config: Av1ExportConfig,
+    encoder: Option<Av1Encoder>,
+    started: bool,
+    progress: Option<(ProgressCallback, usize)>,
+}
+
+impl Av1Exporter {
+    /// Create a new AV1 exporter
+    pub fn new(config: Av1ExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            encoder: None,
+            started: false,
+            progress: None,
+        })
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        self.started = true;
+        Ok(())
+    }
+
+    /// Add a frame to the video
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        if !self.started {
+            return Err(ExportError::VideoEncode("Exporter not started".to_string()));
+        }
+
+        if self.encoder.is_none() {
+            let (width, height) = fit_dimensions(
+                image.width(),
+                image.height(),
+                self.config.width,
+                self.config.height,
+            );
+            let mut encoder = Av1Encoder::new(width, height, &Av1Settings::from(&self.config))?;
+            encoder.progress = self.progress.take();
+            self.encoder = Some(encoder);
+        }
+
+        match self.encoder.as_mut() {
+            Some(encoder) => encoder.send_frame(image),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        let encoder = self.encoder.take().ok_or(ExportError::NoFrames)?;
+        let track = encoder.finish()?;
+        track.write(&self.config.output_path, self.config.container)?;
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to an AV1 video
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: Av1ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let mut exporter = Self::new(config)?;
+        exporter.progress = progress.map(|cb| (cb, png_paths.len()));
+        exporter.start()?;
+
+        for path in png_paths {
+            let img = image::open(path)?.to_rgba8();
+            exporter.add_frame(img)?;
+        }
+
+        exporter.finish()
+    }
+}
+
+/// AV1 WebM entry in the format registry
+///
+/// AV1 in MP4 is available through [`Av1Exporter`]; `.mp4` maps to H.264 here.
+pub(crate) struct Av1Format;
+
+impl Exporter for Av1Format {
+    fn id(&self) -> &'static str {
+        "av1"
+    }
+
+    fn name(&self) -> &'static str {
+        "WebM video (AV1)"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["webm"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            alpha: false,
+            variable_delays: true,
+            max_colors: None,
+            lossy: true,
+            lossless: false,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let mut options = vec![
+            OptionSpec::choice("preset", "Preset", &["fast", "balanced", "quality"], "balanced"),
+            OptionSpec::int(
+                "bitrate_kbps",
+                "Bitrate in kbps (0 = constant quality)",
+                0,
+                100_000,
+                0,
+            ),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let options = &config.options;
+        let preset = match options.choice_or("preset", "balanced") {
+            "fast" => VideoPreset::Fast,
+            "quality" => VideoPreset::Quality,
+            _ => VideoPreset::Balanced,
+        };
+        let av1_config = Av1ExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            timestamps: config.frame_timestamps(png_paths.len()),
+            container: VideoContainer::WebM,
+            preset,
+            bitrate_kbps: options.dimension("bitrate_kbps"),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+        };
+        Av1Exporter::export_from_pngs(png_paths, av1_config, progress)
+    }
+}
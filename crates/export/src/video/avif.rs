@@ -0,0 +1,232 @@
+//! Animated AVIF export
+//!
+//! Frames are AV1-encoded with rav1e and stored as an AVIF image sequence.
+//! Alpha goes into a separate monochrome auxiliary track, which is only
+//! encoded once a frame with transparency arrives.
+
+use super::av1::{Av1Encoder, Av1Settings};
+use super::mp4;
+use crate::metadata::EmbeddedMetadata;
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::fit_dimensions;
+use crate::{ExportError, ExportResult, ProgressCallback};
+use image::RgbaImage;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// AVIF export configuration
+#[derive(Debug, Clone)]
+pub struct AvifExportConfig {
+    pub output_path: PathBuf,
+    pub fps: u8,
+    /// Capture time of each frame, relative to the first; frames past its
+    /// end, or all frames when empty, follow `fps`
+    pub timestamps: Vec<Duration>,
+    /// 0-100, higher is better
+    pub quality: u8,
+    /// 0 = slowest/smallest, 10 = fastest
+    pub speed: u8,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Written as an XMP item
+    pub metadata: EmbeddedMetadata,
+}
+
+impl Default for AvifExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            timestamps: Vec::new(),
+            quality: 80,
+            speed: 6,
+            width: None,
+            height: None,
+            metadata: EmbeddedMetadata::default(),
+        }
+    }
+}
+
+impl AvifExportConfig {
+    fn settings(&self, alpha: bool) -> Av1Settings {
+        let quality = self.quality.min(100) as usize;
+        let quantizer = (100 - quality) * 255 / 100;
+        Av1Settings {
+            fps: self.fps,
+            timestamps: self.timestamps.clone(),
+            speed: self.speed,
+            // Alpha edges are very visible, so keep them sharper than color
+            quantizer: if alpha { quantizer / 2 } else { quantizer },
+            bitrate_kbps: None,
+            alpha,
+        }
+    }
+}
+
+struct AvifEncoders {
+    width: u32,
+    height: u32,
+    color: Av1Encoder,
+    /// Started by the first frame that isn't fully opaque
+    alpha: Option<Av1Encoder>,
+    /// Frames sent to the color encoder
+    frames: usize,
+}
+
+/// Animated AVIF exporter
+pub struct AvifExporter {
+    config: AvifExportConfig,
+    encoders: Option<AvifEncoders>,
+    started: bool,
+    progress: Option<(ProgressCallback, usize)>,
+}
+
+impl AvifExporter {
+    /// Create a new AVIF exporter
+    pub fn new(config: AvifExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            encoders: None,
+            started: false,
+            progress: None,
+        })
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        self.started = true;
+        Ok(())
+    }
+
+    /// Add a frame to the animation
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        if !self.started {
+            return Err(ExportError::VideoEncode("Exporter not started".to_string()));
+        }
+
+        if self.encoders.is_none() {
+            let (width, height) = fit_dimensions(
+                image.width(),
+                image.height(),
+                self.config.width,
+                self.config.height,
+            );
+            let mut color = Av1Encoder::new(width, height, &self.config.settings(false))?;
+            color.progress = self.progress.take();
+            self.encoders = Some(AvifEncoders { width, height, color, alpha: None, frames: 0 });
+        }
+
+        if let Some(encoders) = self.encoders.as_mut() {
+            if encoders.alpha.is_none() && image.pixels().any(|p| p[3] < 255) {
+                // Earlier frames were opaque, so their alpha is solid white
+                let mut alpha =
+                    Av1Encoder::new(encoders.width, encoders.height, &self.config.settings(true))?;
+                let black = image::Rgba([0, 0, 0, 255]);
+                let opaque = RgbaImage::from_pixel(encoders.width, encoders.height, black);
+                for _ in 0..encoders.frames {
+                    alpha.send_frame(opaque.clone())?;
+                }
+                encoders.alpha = Some(alpha);
+            }
+            if let Some(alpha) = encoders.alpha.as_mut() {
+                alpha.send_frame(image.clone())?;
+            }
+            encoders.color.send_frame(image)?;
+            encoders.frames += 1;
+        }
+        Ok(())
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        let encoders = self.encoders.take().ok_or(ExportError::NoFrames)?;
+        let color = encoders.color.finish()?;
+        let alpha = encoders.alpha.map(Av1Encoder::finish).transpose()?;
+        let alpha = alpha.as_ref();
+
+        let xmp = (!self.config.metadata.is_empty()).then(|| self.config.metadata.xmp());
+        mp4::write_avif(&color, alpha, xmp.as_ref().map(|x| x.as_bytes()), &self.config.output_path)?;
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to an animated AVIF
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: AvifExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let mut exporter = Self::new(config)?;
+        exporter.progress = progress.map(|cb| (cb, png_paths.len()));
+        exporter.start()?;
+
+        for path in png_paths {
+            let img = image::open(path)?.to_rgba8();
+            exporter.add_frame(img)?;
+        }
+
+        exporter.finish()
+    }
+}
+
+/// Animated AVIF entry in the format registry
+pub(crate) struct AvifFormat;
+
+impl Exporter for AvifFormat {
+    fn id(&self) -> &'static str {
+        "avif"
+    }
+
+    fn name(&self) -> &'static str {
+        "AVIF"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["avif"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            alpha: true,
+            variable_delays: true,
+            max_colors: None,
+            lossy: true,
+            lossless: false,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = AvifExportConfig::default();
+        let mut options = vec![
+            OptionSpec::int("quality", "Quality", 0, 100, defaults.quality as i64),
+            OptionSpec::int("speed", "Speed (0 = slowest, 10 = fastest)", 0, 10, defaults.speed as i64),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let defaults = AvifExportConfig::default();
+        let options = &config.options;
+        let avif_config = AvifExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            timestamps: config.frame_timestamps(png_paths.len()),
+            quality: options.u8_or("quality", defaults.quality),
+            speed: options.u8_or("speed", defaults.speed),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+            metadata: config.metadata.clone(),
+        };
+        AvifExporter::export_from_pngs(png_paths, avif_config, progress)
+    }
+}
@@ -0,0 +1,369 @@
+//! H.264 video export using OpenH264 (built from source)
+
+use super::{VideoCodec, VideoContainer, VideoSample, VideoTrack};
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, frame_time, resize_to};
+use crate::yuv::Yuv420;
+use crate::{ExportError, ExportResult, ProgressCallback};
+use image::RgbaImage;
+use openh264::encoder::{Encoder, EncoderConfig};
+use openh264::formats::YUVBuffer;
+use openh264::OpenH264API;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const NAL_IDR: u8 = 5;
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+const NAL_AUD: u8 = 9;
+
+/// H.264 export configuration
+#[derive(Debug, Clone)]
+pub struct H264ExportConfig {
+    pub output_path: PathBuf,
+    pub fps: u8,
+    /// Capture time of each frame, relative to the first; frames past its
+    /// end, or all frames when empty, follow `fps`
+    pub timestamps: Vec<Duration>,
+    /// Target bitrate; `None` picks one from the frame size
+    pub bitrate_kbps: Option<u32>,
+    /// Frames between keyframes; 0 means only the first frame
+    pub keyframe_interval: u32,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Default for H264ExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            timestamps: Vec::new(),
+            bitrate_kbps: None,
+            keyframe_interval: 30,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+/// Round up to the next even number; 4:2:0 needs even dimensions
+fn even(v: u32) -> u32 {
+    v.div_ceil(2) * 2
+}
+
+/// Streaming OpenH264 wrapper that collects samples for the muxer
+struct H264Encoder {
+    encoder: Encoder,
+    /// Output size before padding
+    width: u32,
+    height: u32,
+    /// Encoded size
+    coded_width: u32,
+    coded_height: u32,
+    fps: u8,
+    timestamps: Vec<Duration>,
+    keyframe_interval: u32,
+    frame_index: u64,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    samples: Vec<VideoSample>,
+    progress: Option<(ProgressCallback, usize)>,
+}
+
+impl H264Encoder {
+    fn new(width: u32, height: u32, config: &H264ExportConfig) -> ExportResult<Self> {
+        let coded_width = even(width);
+        let coded_height = even(height);
+        let fps = config.fps.max(1);
+
+        // Roughly 0.1 bits per pixel is plenty for screen content
+        let bitrate_bps = match config.bitrate_kbps {
+            Some(kbps) => kbps.saturating_mul(1000),
+            None => ((coded_width as u64 * coded_height as u64 * fps as u64) / 10)
+                .clamp(500_000, 20_000_000) as u32,
+        };
+
+        let encoder_config =
+            EncoderConfig::new().set_bitrate_bps(bitrate_bps).max_frame_rate(fps as f32);
+
+        let encoder = Encoder::with_api_config(OpenH264API::from_source(), encoder_config)
+            .map_err(|e| ExportError::VideoEncode(e.to_string()))?;
+
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            coded_width,
+            coded_height,
+            fps,
+            timestamps: config.timestamps.clone(),
+            keyframe_interval: config.keyframe_interval,
+            frame_index: 0,
+            sps: None,
+            pps: None,
+            samples: Vec::new(),
+            progress: None,
+        })
+    }
+
+    fn send_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        let image = resize_to(image, self.width, self.height);
+        let yuv = Yuv420::from_rgba(&image, self.coded_width as usize, self.coded_height as usize);
+
+        let mut planar = yuv.y;
+        planar.extend_from_slice(&yuv.u);
+        planar.extend_from_slice(&yuv.v);
+        let buffer = YUVBuffer::from_vec(planar, self.coded_width as usize, self.coded_height as usize);
+
+        if self.keyframe_interval > 0
+            && self.frame_index > 0
+            && self.frame_index.is_multiple_of(self.keyframe_interval as u64)
+        {
+            self.encoder.force_intra_frame();
+        }
+
+        let bitstream = self.encoder
+            .encode(&buffer)
+            .map_err(|e| ExportError::VideoEncode(e.to_string()))?
+            .to_vec();
+
+        let timestamp = self.frame_time(self.frame_index);
+        self.frame_index += 1;
+        self.push_access_unit(&bitstream, timestamp);
+
+        if let Some((ref cb, total)) = self.progress {
+            cb(self.frame_index as f32 / total.max(1) as f32);
+        }
+
+        Ok(())
+    }
+
+    /// Presentation time of frame `index` in seconds
+    fn frame_time(&self, index: u64) -> f64 {
+        frame_time(&self.timestamps, self.fps, index as usize)
+    }
+
+    /// Convert an Annex B access unit into a length-prefixed MP4 sample,
+    /// moving the parameter sets into the track header.
+    fn push_access_unit(&mut self, annex_b: &[u8], timestamp: f64) {
+        let mut data = Vec::with_capacity(annex_b.len());
+        let mut keyframe = false;
+
+        for nal in split_annex_b(annex_b) {
+            match nal[0] & 0x1F {
+                NAL_SPS => {
+                    self.sps.get_or_insert_with(|| nal.to_vec());
+                }
+                NAL_PPS => {
+                    self.pps.get_or_insert_with(|| nal.to_vec());
+                }
+                NAL_AUD => {}
+                nal_type => {
+                    keyframe |= nal_type == NAL_IDR;
+                    data.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                    data.extend_from_slice(nal);
+                }
+            }
+        }
+
+        // Rate control may skip a frame entirely; the previous sample then
+        // simply lasts longer.
+        if !data.is_empty() {
+            self.samples.push(VideoSample { data, timestamp, keyframe });
+        }
+    }
+
+    fn finish(self) -> ExportResult<VideoTrack> {
+        if self.samples.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        // The last sample also covers any trailing frames rate control skipped
+        let end = self.frame_time(self.frame_index);
+        let last = self.samples.last().map_or(0.0, |s| s.timestamp);
+
+        let (sps, pps) = match (self.sps, self.pps) {
+            (Some(sps), Some(pps)) if sps.len() >= 4 => (sps, pps),
+            _ => return Err(ExportError::VideoEncode("Missing SPS/PPS".to_string())),
+        };
+
+        Ok(VideoTrack {
+            codec: VideoCodec::Avc { sps, pps },
+            width: self.coded_width,
+            height: self.coded_height,
+            samples: self.samples,
+            frame_duration: (end - last).max(0.0),
+        })
+    }
+}
+
+/// Split an Annex B byte stream into NAL units without start codes
+fn split_annex_b(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+
+    let mut ends: Vec<usize> = starts.iter().skip(1).map(|&s| s - 3).collect();
+    ends.push(data.len());
+
+    starts.into_iter().zip(ends).filter_map(move |(start, end)| {
+        // A four-byte start code leaves a zero byte at the end of the previous NAL
+        let mut end = end;
+        while end > start && data[end - 1] == 0 {
+            end -= 1;
+        }
+        (end > start).then(|| &data[start..end])
+    })
+}
+
+/// H.264 video exporter (MP4 only)
+pub struct H264Exporter {
+    config: H264ExportConfig,
+    encoder: Option<H264Encoder>,
+    started: bool,
+    progress: Option<(ProgressCallback, usize)>,
+}
+
+impl H264Exporter {
+    /// Create a new H.264 exporter
+    pub fn new(config: H264ExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            encoder: None,
+            started: false,
+            progress: None,
+        })
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        self.started = true;
+        Ok(())
+    }
+
+    /// Add a frame to the video
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        if !self.started {
+            return Err(ExportError::VideoEncode("Exporter not started".to_string()));
+        }
+
+        if self.encoder.is_none() {
+            let (width, height) = fit_dimensions(
+                image.width(),
+                image.height(),
+                self.config.width,
+                self.config.height,
+            );
+            let mut encoder = H264Encoder::new(width, height, &self.config)?;
+            encoder.progress = self.progress.take();
+            self.encoder = Some(encoder);
+        }
+
+        match self.encoder.as_mut() {
+            Some(encoder) => encoder.send_frame(image),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        let encoder = self.encoder.take().ok_or(ExportError::NoFrames)?;
+        let track = encoder.finish()?;
+        track.write(&self.config.output_path, VideoContainer::Mp4)?;
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to an H.264 MP4
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: H264ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let mut exporter = Self::new(config)?;
+        exporter.progress = progress.map(|cb| (cb, png_paths.len()));
+        exporter.start()?;
+
+        for path in png_paths {
+            let img = image::open(path)?.to_rgba8();
+            exporter.add_frame(img)?;
+        }
+
+        exporter.finish()
+    }
+}
+
+/// H.264 entry in the format registry
+pub(crate) struct H264Format;
+
+impl Exporter for H264Format {
+    fn id(&self) -> &'static str {
+        "h264"
+    }
+
+    fn name(&self) -> &'static str {
+        "MP4 video (H.264)"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["mp4"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            alpha: false,
+            variable_delays: true,
+            max_colors: None,
+            lossy: true,
+            lossless: false,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = H264ExportConfig::default();
+        let mut options = vec![
+            OptionSpec::int("bitrate_kbps", "Bitrate in kbps (0 = auto)", 0, 100_000, 0),
+            OptionSpec::int(
+                "keyframe_interval",
+                "Keyframe interval (0 = first frame only)",
+                0,
+                3600,
+                defaults.keyframe_interval as i64,
+            ),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let defaults = H264ExportConfig::default();
+        let options = &config.options;
+        let h264_config = H264ExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            timestamps: config.frame_timestamps(png_paths.len()),
+            bitrate_kbps: options.dimension("bitrate_kbps"),
+            keyframe_interval: options.u32_or("keyframe_interval", defaults.keyframe_interval),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+        };
+        H264Exporter::export_from_pngs(png_paths, h264_config, progress)
+    }
+}
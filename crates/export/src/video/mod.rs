@@ -0,0 +1,92 @@
+//! Video export
+//!
+//! Encoders hand their compressed samples to small built-in muxers, so no
+//! external tools are needed to produce a playable file.
+
+mod av1;
+mod avif;
+mod h264;
+mod mp4;
+mod webm;
+
+pub use av1::{Av1ExportConfig, Av1Exporter};
+pub use avif::{AvifExportConfig, AvifExporter};
+pub use h264::{H264ExportConfig, H264Exporter};
+
+pub(crate) use av1::Av1Format;
+pub(crate) use avif::AvifFormat;
+pub(crate) use h264::H264Format;
+
+use crate::ExportResult;
+use std::path::Path;
+
+/// Video container format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoContainer {
+    Mp4,
+    WebM,
+}
+
+/// Encoder speed/size trade-off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoPreset {
+    /// Fastest encode, largest files
+    Fast,
+    Balanced,
+    /// Slowest encode, smallest files
+    Quality,
+}
+
+/// Codec-specific data needed by the muxers
+pub(crate) enum VideoCodec {
+    /// AV1 with its `av1C` configuration record
+    Av1 { config: Vec<u8> },
+    /// H.264 with its parameter sets (NAL units without start codes)
+    Avc { sps: Vec<u8>, pps: Vec<u8> },
+}
+
+/// One compressed frame
+pub(crate) struct VideoSample {
+    pub data: Vec<u8>,
+    /// Presentation time in seconds
+    pub timestamp: f64,
+    pub keyframe: bool,
+}
+
+/// Everything a muxer needs to write a single-track video file
+pub(crate) struct VideoTrack {
+    pub codec: VideoCodec,
+    pub width: u32,
+    pub height: u32,
+    pub samples: Vec<VideoSample>,
+    /// Duration given to the last sample, in seconds
+    pub frame_duration: f64,
+}
+
+impl VideoTrack {
+    /// Duration of every sample in seconds, derived from the next timestamp
+    pub fn sample_durations(&self) -> Vec<f64> {
+        let mut durations: Vec<f64> = self
+            .samples
+            .windows(2)
+            .map(|w| (w[1].timestamp - w[0].timestamp).max(0.0))
+            .collect();
+        if !self.samples.is_empty() {
+            durations.push(self.frame_duration);
+        }
+        durations
+    }
+
+    /// Total duration in seconds
+    pub fn duration(&self) -> f64 {
+        self.sample_durations().iter().sum()
+    }
+
+    /// Write the track to `path` in the given container
+    pub fn write(&self, path: &Path, container: VideoContainer) -> ExportResult<()> {
+        match container {
+            VideoContainer::Mp4 => mp4::write(self, path),
+            VideoContainer::WebM => webm::write(self, path),
+        }
+    }
+}
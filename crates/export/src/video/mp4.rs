@@ -0,0 +1,640 @@
+//! Minimal ISO-BMFF (MP4) muxer
+//!
+//! Writes the `moov` box ahead of `mdat` ("faststart"), so browsers can start
+//! playback before the download ends. The same box writer also produces
+//! animated AVIF, which is an ISO-BMFF image sequence.
+
+use super::{VideoCodec, VideoTrack};
+use crate::ExportResult;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Media timescale in ticks per second
+const TIMESCALE: u32 = 90_000;
+/// Movie header timescale (milliseconds)
+const MOVIE_TIMESCALE: u32 = 1000;
+
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+const ALPHA_URN: &[u8] = b"urn:mpeg:mpegB:cicp:systems:auxiliary:alpha\0";
+
+/// How a track is presented
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TrackRole {
+    /// Regular video track
+    Video,
+    /// Color track of an AVIF image sequence
+    Image,
+    /// Alpha plane of an AVIF image sequence, auxiliary to track 1
+    Alpha,
+}
+
+struct TrackSpec<'a> {
+    track: &'a VideoTrack,
+    role: TrackRole,
+}
+
+impl TrackSpec<'_> {
+    fn payload_len(&self) -> u64 {
+        self.track.samples.iter().map(|s| s.data.len() as u64).sum()
+    }
+}
+
+/// Write `track` as an MP4 file
+pub(crate) fn write(track: &VideoTrack, path: &Path) -> ExportResult<()> {
+    let codec_brand: &[u8; 4] = match track.codec {
+        VideoCodec::Av1 { .. } => b"av01",
+        VideoCodec::Avc { .. } => b"avc1",
+    };
+    let ftyp = ftyp(b"isom", &[b"isom", b"iso2", codec_brand, b"mp41"]);
+    let tracks = [TrackSpec { track, role: TrackRole::Video }];
+    write_file(path, &ftyp, &tracks, false, None)
+}
+
+/// Write an animated AVIF with an optional alpha track and XMP packet
+///
+/// The first frame is also exposed as the primary still image, so viewers
+/// without sequence support still show something.
+pub(crate) fn write_avif(
+    color: &VideoTrack,
+    alpha: Option<&VideoTrack>,
+    xmp: Option<&[u8]>,
+    path: &Path,
+) -> ExportResult<()> {
+    let ftyp = ftyp(b"avis", &[b"avif", b"avis", b"msf1", b"iso8", b"mif1", b"miaf", b"MA1B"]);
+    let mut tracks = vec![TrackSpec { track: color, role: TrackRole::Image }];
+    if let Some(alpha) = alpha {
+        tracks.push(TrackSpec { track: alpha, role: TrackRole::Alpha });
+    }
+    write_file(path, &ftyp, &tracks, true, xmp)
+}
+
+/// `xmp` is stored after the samples as an item of the `meta` box
+fn write_file(
+    path: &Path,
+    ftyp: &[u8],
+    tracks: &[TrackSpec],
+    with_meta: bool,
+    xmp: Option<&[u8]>,
+) -> ExportResult<()> {
+    let xmp_len = xmp.map_or(0, |x| x.len() as u64);
+    let payload_len: u64 = tracks.iter().map(TrackSpec::payload_len).sum::<u64>() + xmp_len;
+    let large = payload_len + 16 > u32::MAX as u64;
+    let mdat_header_len = if large { 16 } else { 8 };
+
+    // Chunk offsets depend on the size of the header boxes, which does not
+    // change with the offset values, so build them twice.
+    let build = |base: u64| -> Vec<u8> {
+        let mut offsets = Vec::with_capacity(tracks.len());
+        let mut offset = base;
+        for spec in tracks {
+            offsets.push(offset);
+            offset += spec.payload_len();
+        }
+
+        let mut out = Vec::new();
+        if with_meta {
+            meta(&mut out, tracks, &offsets, xmp.map(|x| (x, offset)), large);
+        }
+        moov(&mut out, tracks, &offsets, large);
+        out
+    };
+    let probe = build(0);
+    let header = build((ftyp.len() + probe.len() + mdat_header_len) as u64);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(ftyp)?;
+    out.write_all(&header)?;
+    if large {
+        out.write_all(&1u32.to_be_bytes())?;
+        out.write_all(b"mdat")?;
+        out.write_all(&(payload_len + 16).to_be_bytes())?;
+    } else {
+        out.write_all(&((payload_len + 8) as u32).to_be_bytes())?;
+        out.write_all(b"mdat")?;
+    }
+    for spec in tracks {
+        for sample in &spec.track.samples {
+            out.write_all(&sample.data)?;
+        }
+    }
+    if let Some(xmp) = xmp {
+        out.write_all(xmp)?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Append a box whose contents are produced by `f`
+fn write_box(out: &mut Vec<u8>, kind: &[u8; 4], f: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(kind);
+    f(out);
+    let size = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+/// Append a full box (box with version and flags)
+fn write_full_box(
+    out: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    f: impl FnOnce(&mut Vec<u8>),
+) {
+    write_box(out, kind, |out| {
+        out.push(version);
+        out.extend_from_slice(&flags.to_be_bytes()[1..]);
+        f(out);
+    });
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_offset(out: &mut Vec<u8>, v: u64, large: bool) {
+    if large {
+        out.extend_from_slice(&v.to_be_bytes());
+    } else {
+        put_u32(out, v as u32);
+    }
+}
+
+fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_box(&mut out, b"ftyp", |out| {
+        out.extend_from_slice(major);
+        put_u32(out, 0);
+        for brand in compatible {
+            out.extend_from_slice(*brand);
+        }
+    });
+    out
+}
+
+fn codec_config(track: &VideoTrack) -> &[u8] {
+    match &track.codec {
+        VideoCodec::Av1 { config } => config,
+        VideoCodec::Avc { sps, .. } => sps,
+    }
+}
+
+/// Still-image items pointing at the first sample of each track, plus an
+/// XMP item for `xmp` and its file offset
+fn meta(
+    out: &mut Vec<u8>,
+    tracks: &[TrackSpec],
+    offsets: &[u64],
+    xmp: Option<(&[u8], u64)>,
+    large: bool,
+) {
+    let color = tracks[0].track;
+    let has_alpha = tracks.len() > 1;
+    let item_count = tracks.len() as u16 + xmp.is_some() as u16;
+    let xmp_item = tracks.len() as u16 + 1;
+
+    write_full_box(out, b"meta", 0, 0, |out| {
+        write_full_box(out, b"hdlr", 0, 0, |out| {
+            put_u32(out, 0);
+            out.extend_from_slice(b"pict");
+            out.extend_from_slice(&[0; 12]);
+            out.push(0);
+        });
+
+        write_full_box(out, b"pitm", 0, 0, |out| put_u16(out, 1));
+
+        write_full_box(out, b"iloc", 0, 0, |out| {
+            out.push(if large { 0x84 } else { 0x44 }); // offset and length sizes
+            out.push(0); // no base offset
+            put_u16(out, item_count);
+            for (i, spec) in tracks.iter().enumerate() {
+                put_u16(out, i as u16 + 1);
+                put_u16(out, 0); // this file
+                put_u16(out, 1); // one extent
+                put_offset(out, offsets[i], large);
+                put_u32(out, spec.track.samples[0].data.len() as u32);
+            }
+            if let Some((data, offset)) = xmp {
+                put_u16(out, xmp_item);
+                put_u16(out, 0);
+                put_u16(out, 1);
+                put_offset(out, offset, large);
+                put_u32(out, data.len() as u32);
+            }
+        });
+
+        write_full_box(out, b"iinf", 0, 0, |out| {
+            put_u16(out, item_count);
+            for (i, spec) in tracks.iter().enumerate() {
+                write_full_box(out, b"infe", 2, 0, |out| {
+                    put_u16(out, i as u16 + 1);
+                    put_u16(out, 0);
+                    out.extend_from_slice(b"av01");
+                    out.extend_from_slice(if spec.role == TrackRole::Alpha { b"Alpha\0" } else { b"Color\0" });
+                });
+            }
+            if xmp.is_some() {
+                write_full_box(out, b"infe", 2, 0, |out| {
+                    put_u16(out, xmp_item);
+                    put_u16(out, 0);
+                    out.extend_from_slice(b"mime");
+                    out.extend_from_slice(b"XMP\0");
+                    out.extend_from_slice(b"application/rdf+xml\0");
+                });
+            }
+        });
+
+        if has_alpha {
+            write_full_box(out, b"iref", 0, 0, |out| {
+                write_box(out, b"auxl", |out| {
+                    put_u16(out, 2);
+                    put_u16(out, 1);
+                    put_u16(out, 1);
+                });
+            });
+        }
+
+        write_box(out, b"iprp", |out| {
+            write_box(out, b"ipco", |out| {
+                // 1: ispe, 2: av1C, 3: pixi, 4: colr
+                write_full_box(out, b"ispe", 0, 0, |out| {
+                    put_u32(out, color.width);
+                    put_u32(out, color.height);
+                });
+                write_box(out, b"av1C", |out| out.extend_from_slice(codec_config(color)));
+                write_full_box(out, b"pixi", 0, 0, |out| out.extend_from_slice(&[3, 8, 8, 8]));
+                colr(out);
+
+                // 5: av1C, 6: pixi, 7: auxC
+                if let Some(alpha) = tracks.get(1) {
+                    write_box(out, b"av1C", |out| out.extend_from_slice(codec_config(alpha.track)));
+                    write_full_box(out, b"pixi", 0, 0, |out| out.extend_from_slice(&[1, 8]));
+                    write_full_box(out, b"auxC", 0, 0, |out| out.extend_from_slice(ALPHA_URN));
+                }
+            });
+
+            write_full_box(out, b"ipma", 0, 0, |out| {
+                put_u32(out, tracks.len() as u32);
+                put_u16(out, 1);
+                out.extend_from_slice(&[4, 1, 0x80 | 2, 3, 4]);
+                if has_alpha {
+                    put_u16(out, 2);
+                    out.extend_from_slice(&[4, 1, 0x80 | 5, 6, 0x80 | 7]);
+                }
+            });
+        });
+    });
+}
+
+/// BT.709 limited-range color information
+fn colr(out: &mut Vec<u8>) {
+    write_box(out, b"colr", |out| {
+        out.extend_from_slice(b"nclx");
+        put_u16(out, 1); // primaries
+        put_u16(out, 1); // transfer characteristics
+        put_u16(out, 1); // matrix coefficients
+        out.push(0); // limited range
+    });
+}
+
+fn moov(out: &mut Vec<u8>, tracks: &[TrackSpec], offsets: &[u64], large: bool) {
+    let movie_duration = tracks
+        .iter()
+        .map(|spec| (spec.track.duration() * MOVIE_TIMESCALE as f64).round() as u32)
+        .max()
+        .unwrap_or(0);
+
+    write_box(out, b"moov", |out| {
+        write_full_box(out, b"mvhd", 0, 0, |out| {
+            put_u32(out, 0); // creation time
+            put_u32(out, 0); // modification time
+            put_u32(out, MOVIE_TIMESCALE);
+            put_u32(out, movie_duration);
+            put_u32(out, 0x0001_0000); // rate 1.0
+            put_u16(out, 0x0100); // volume 1.0
+            out.extend_from_slice(&[0; 10]);
+            for v in UNITY_MATRIX {
+                put_u32(out, v);
+            }
+            out.extend_from_slice(&[0; 24]);
+            put_u32(out, tracks.len() as u32 + 1); // next track id
+        });
+
+        for (i, spec) in tracks.iter().enumerate() {
+            trak(out, spec, i as u32 + 1, offsets[i], large);
+        }
+    });
+}
+
+fn trak(out: &mut Vec<u8>, spec: &TrackSpec, track_id: u32, data_offset: u64, large: bool) {
+    let track = spec.track;
+    let durations: Vec<u32> = track
+        .sample_durations()
+        .iter()
+        .map(|d| ((d * TIMESCALE as f64).round() as u32).max(1))
+        .collect();
+    let media_duration: u64 = durations.iter().map(|&d| d as u64).sum();
+    let movie_duration = (media_duration * MOVIE_TIMESCALE as u64 / TIMESCALE as u64) as u32;
+
+    write_box(out, b"trak", |out| {
+        write_full_box(out, b"tkhd", 0, 0x3, |out| {
+            put_u32(out, 0);
+            put_u32(out, 0);
+            put_u32(out, track_id);
+            put_u32(out, 0);
+            put_u32(out, movie_duration);
+            out.extend_from_slice(&[0; 8]);
+            put_u16(out, 0); // layer
+            put_u16(out, 0); // alternate group
+            put_u16(out, 0); // volume
+            put_u16(out, 0);
+            for v in UNITY_MATRIX {
+                put_u32(out, v);
+            }
+            put_u32(out, track.width << 16);
+            put_u32(out, track.height << 16);
+        });
+
+        if spec.role == TrackRole::Alpha {
+            write_box(out, b"tref", |out| {
+                write_box(out, b"auxl", |out| put_u32(out, 1));
+            });
+        }
+
+        if spec.role != TrackRole::Video {
+            // Flag 1 asks image sequence players to repeat the edit forever
+            write_box(out, b"edts", |out| {
+                write_full_box(out, b"elst", 0, 1, |out| {
+                    put_u32(out, 1);
+                    put_u32(out, movie_duration);
+                    put_u32(out, 0); // media time
+                    put_u32(out, 0x0001_0000); // rate 1.0
+                });
+            });
+        }
+
+        write_box(out, b"mdia", |out| {
+            write_full_box(out, b"mdhd", 0, 0, |out| {
+                put_u32(out, 0);
+                put_u32(out, 0);
+                put_u32(out, TIMESCALE);
+                put_u32(out, media_duration as u32);
+                put_u16(out, 0x55C4); // "und"
+                put_u16(out, 0);
+            });
+
+            let (handler, name): (&[u8; 4], &[u8]) = match spec.role {
+                TrackRole::Video => (b"vide", b"VideoHandler\0"),
+                TrackRole::Image => (b"pict", b"PictureHandler\0"),
+                TrackRole::Alpha => (b"auxv", b"AuxiliaryHandler\0"),
+            };
+            write_full_box(out, b"hdlr", 0, 0, |out| {
+                put_u32(out, 0);
+                out.extend_from_slice(handler);
+                out.extend_from_slice(&[0; 12]);
+                out.extend_from_slice(name);
+            });
+
+            write_box(out, b"minf", |out| {
+                write_full_box(out, b"vmhd", 0, 1, |out| {
+                    out.extend_from_slice(&[0; 8]);
+                });
+
+                write_box(out, b"dinf", |out| {
+                    write_full_box(out, b"dref", 0, 0, |out| {
+                        put_u32(out, 1);
+                        // Media data is in this file
+                        write_full_box(out, b"url ", 0, 1, |_| {});
+                    });
+                });
+
+                write_box(out, b"stbl", |out| {
+                    stbl(out, spec, &durations, data_offset, large);
+                });
+            });
+        });
+    });
+}
+
+fn stbl(out: &mut Vec<u8>, spec: &TrackSpec, durations: &[u32], data_offset: u64, large: bool) {
+    let track = spec.track;
+
+    write_full_box(out, b"stsd", 0, 0, |out| {
+        put_u32(out, 1);
+        sample_entry(out, spec);
+    });
+
+    // Run-length encoded sample durations
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for &d in durations {
+        match runs.last_mut() {
+            Some((count, delta)) if *delta == d => *count += 1,
+            _ => runs.push((1, d)),
+        }
+    }
+    write_full_box(out, b"stts", 0, 0, |out| {
+        put_u32(out, runs.len() as u32);
+        for (count, delta) in &runs {
+            put_u32(out, *count);
+            put_u32(out, *delta);
+        }
+    });
+
+    // Sync samples; omitting the box means every sample is a keyframe
+    if track.samples.iter().any(|s| !s.keyframe) {
+        let sync: Vec<u32> = track
+            .samples
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.keyframe)
+            .map(|(i, _)| i as u32 + 1)
+            .collect();
+        write_full_box(out, b"stss", 0, 0, |out| {
+            put_u32(out, sync.len() as u32);
+            for n in sync {
+                put_u32(out, n);
+            }
+        });
+    }
+
+    // All samples of a track live in one chunk
+    write_full_box(out, b"stsc", 0, 0, |out| {
+        put_u32(out, 1);
+        put_u32(out, 1);
+        put_u32(out, track.samples.len() as u32);
+        put_u32(out, 1);
+    });
+
+    write_full_box(out, b"stsz", 0, 0, |out| {
+        put_u32(out, 0);
+        put_u32(out, track.samples.len() as u32);
+        for sample in &track.samples {
+            put_u32(out, sample.data.len() as u32);
+        }
+    });
+
+    let kind = if large { b"co64" } else { b"stco" };
+    write_full_box(out, kind, 0, 0, |out| {
+        put_u32(out, 1);
+        put_offset(out, data_offset, large);
+    });
+}
+
+fn sample_entry(out: &mut Vec<u8>, spec: &TrackSpec) {
+    let track = spec.track;
+    let (kind, name): (&[u8; 4], &str) = match track.codec {
+        VideoCodec::Av1 { .. } => (b"av01", "AOM AV1"),
+        VideoCodec::Avc { .. } => (b"avc1", "OpenH264"),
+    };
+
+    write_box(out, kind, |out| {
+        out.extend_from_slice(&[0; 6]);
+        put_u16(out, 1); // data reference index
+        out.extend_from_slice(&[0; 16]);
+        put_u16(out, track.width as u16);
+        put_u16(out, track.height as u16);
+        put_u32(out, 0x0048_0000); // 72 dpi
+        put_u32(out, 0x0048_0000);
+        put_u32(out, 0);
+        put_u16(out, 1); // frame count
+        let mut compressor = [0u8; 32];
+        compressor[0] = name.len() as u8;
+        compressor[1..=name.len()].copy_from_slice(name.as_bytes());
+        out.extend_from_slice(&compressor);
+        put_u16(out, 0x0018); // depth
+        put_u16(out, 0xFFFF);
+
+        match &track.codec {
+            VideoCodec::Av1 { config } => {
+                write_box(out, b"av1C", |out| out.extend_from_slice(config));
+            }
+            VideoCodec::Avc { sps, pps } => {
+                write_box(out, b"avcC", |out| {
+                    out.push(1); // configuration version
+                    out.extend_from_slice(&sps[1..4]); // profile, compatibility, level
+                    out.push(0xFF); // 4-byte NAL lengths
+                    out.push(0xE1); // one SPS
+                    put_u16(out, sps.len() as u16);
+                    out.extend_from_slice(sps);
+                    out.push(1); // one PPS
+                    put_u16(out, pps.len() as u16);
+                    out.extend_from_slice(pps);
+                });
+            }
+        }
+
+        match spec.role {
+            TrackRole::Video => {}
+            TrackRole::Image => colr(out),
+            TrackRole::Alpha => {
+                write_full_box(out, b"auxi", 0, 0, |out| out.extend_from_slice(ALPHA_URN));
+            }
+        }
+
+        if spec.role != TrackRole::Video {
+            // Coding constraints: intra prediction used, up to 15 references
+            write_full_box(out, b"ccst", 0, 0, |out| put_u32(out, 0x7C00_0000));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::VideoSample;
+
+    fn track() -> VideoTrack {
+        let sample = |fill: u8, len: usize, timestamp: f64, keyframe: bool| VideoSample {
+            data: vec![fill; len],
+            timestamp,
+            keyframe,
+        };
+        VideoTrack {
+            codec: VideoCodec::Avc { sps: vec![0x67, 0x42, 0xC0, 0x1E], pps: vec![0x68, 0xCE] },
+            width: 64,
+            height: 48,
+            samples: vec![
+                sample(1, 40, 0.0, true),
+                sample(2, 7, 0.1, false),
+                sample(3, 12, 0.15, false),
+            ],
+            frame_duration: 0.3,
+        }
+    }
+
+    /// Type and contents of each box in `data`, checking the sizes add up
+    fn boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut boxes = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let size = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            assert!(size >= 8 && size <= rest.len(), "box size {size} out of bounds");
+            boxes.push((rest[4..8].try_into().unwrap(), &rest[8..size]));
+            rest = &rest[size..];
+        }
+        boxes
+    }
+
+    /// Contents of the only box at `path`, e.g. `["moov", "trak"]`
+    fn find<'a>(data: &'a [u8], path: &[&str]) -> &'a [u8] {
+        path.iter().fold(data, |data, kind| {
+            let found: Vec<_> =
+                boxes(data).into_iter().filter(|(k, _)| k == kind.as_bytes()).collect();
+            assert_eq!(found.len(), 1, "expected one {kind} box");
+            found[0].1
+        })
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_faststart_box_layout() {
+        let path = std::env::temp_dir().join(format!("wingif-mp4-{}.mp4", std::process::id()));
+        let track = track();
+        write(&track, &path).unwrap();
+        let file = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let kinds: Vec<_> = boxes(&file).iter().map(|(k, _)| *k).collect();
+        assert_eq!(kinds, [*b"ftyp", *b"moov", *b"mdat"]);
+        assert_eq!(&find(&file, &["ftyp"])[..4], b"isom");
+
+        let payload: Vec<u8> = track.samples.iter().flat_map(|s| s.data.clone()).collect();
+        let mdat = find(&file, &["mdat"]);
+        assert_eq!(mdat, payload.as_slice());
+
+        let stbl = find(&file, &["moov", "trak", "mdia", "minf", "stbl"]);
+        let stsd = find(stbl, &["stsd"]);
+        assert_eq!(&stsd[12..16], b"avc1");
+
+        // Full boxes start with version and flags, then the entry count
+        let stts = find(stbl, &["stts"]);
+        let runs: Vec<_> = (0..u32_at(stts, 4) as usize)
+            .map(|i| (u32_at(stts, 8 + i * 8), u32_at(stts, 12 + i * 8)))
+            .collect();
+        assert_eq!(runs, [(1, 9000), (1, 4500), (1, 27000)]);
+
+        let stss = find(stbl, &["stss"]);
+        assert_eq!((u32_at(stss, 4), u32_at(stss, 8)), (1, 1));
+
+        let stsz = find(stbl, &["stsz"]);
+        let sizes: Vec<_> =
+            (0..u32_at(stsz, 8) as usize).map(|i| u32_at(stsz, 12 + i * 4)).collect();
+        assert_eq!(sizes, [40, 7, 12]);
+
+        // The chunk offset points at the start of the mdat payload
+        let stco = find(stbl, &["stco"]);
+        let offset = u32_at(stco, 8) as usize;
+        assert_eq!(&file[offset..offset + payload.len()], payload.as_slice());
+    }
+}
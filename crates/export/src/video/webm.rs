@@ -0,0 +1,280 @@
+//! Minimal WebM (Matroska) muxer
+//!
+//! The whole file is assembled in memory, so every element is written with
+//! its final size and no seeking is required.
+
+use super::{VideoCodec, VideoTrack};
+use crate::{ExportError, ExportResult};
+use std::fs;
+use std::path::Path;
+
+/// Timestamps are stored in milliseconds
+const TIMESTAMP_SCALE_NS: u64 = 1_000_000;
+/// Start a new cluster at the next keyframe after this many milliseconds
+const CLUSTER_TARGET_MS: i64 = 5_000;
+
+mod id {
+    pub const EBML: u32 = 0x1A45_DFA3;
+    pub const EBML_VERSION: u32 = 0x4286;
+    pub const EBML_READ_VERSION: u32 = 0x42F7;
+    pub const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+    pub const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+    pub const DOC_TYPE: u32 = 0x4282;
+    pub const DOC_TYPE_VERSION: u32 = 0x4287;
+    pub const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+    pub const SEGMENT: u32 = 0x1853_8067;
+    pub const INFO: u32 = 0x1549_A966;
+    pub const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+    pub const DURATION: u32 = 0x4489;
+    pub const MUXING_APP: u32 = 0x4D80;
+    pub const WRITING_APP: u32 = 0x5741;
+    pub const TRACKS: u32 = 0x1654_AE6B;
+    pub const TRACK_ENTRY: u32 = 0xAE;
+    pub const TRACK_NUMBER: u32 = 0xD7;
+    pub const TRACK_UID: u32 = 0x73C5;
+    pub const TRACK_TYPE: u32 = 0x83;
+    pub const FLAG_LACING: u32 = 0x9C;
+    pub const CODEC_ID: u32 = 0x86;
+    pub const CODEC_PRIVATE: u32 = 0x63A2;
+    pub const VIDEO: u32 = 0xE0;
+    pub const PIXEL_WIDTH: u32 = 0xB0;
+    pub const PIXEL_HEIGHT: u32 = 0xBA;
+    pub const CLUSTER: u32 = 0x1F43_B675;
+    pub const TIMESTAMP: u32 = 0xE7;
+    pub const SIMPLE_BLOCK: u32 = 0xA3;
+}
+
+/// Write `track` as a WebM file
+pub(crate) fn write(track: &VideoTrack, path: &Path) -> ExportResult<()> {
+    let (codec_id, codec_private) = match &track.codec {
+        VideoCodec::Av1 { config } => ("V_AV1", config.as_slice()),
+        VideoCodec::Avc { .. } => {
+            return Err(ExportError::VideoEncode("WebM does not support H.264".to_string()));
+        }
+    };
+
+    let mut file = Vec::new();
+    element(&mut file, id::EBML, |out| {
+        uint_element(out, id::EBML_VERSION, 1);
+        uint_element(out, id::EBML_READ_VERSION, 1);
+        uint_element(out, id::EBML_MAX_ID_LENGTH, 4);
+        uint_element(out, id::EBML_MAX_SIZE_LENGTH, 8);
+        bytes_element(out, id::DOC_TYPE, b"webm");
+        uint_element(out, id::DOC_TYPE_VERSION, 4);
+        uint_element(out, id::DOC_TYPE_READ_VERSION, 2);
+    });
+
+    element(&mut file, id::SEGMENT, |out| {
+        element(out, id::INFO, |out| {
+            uint_element(out, id::TIMESTAMP_SCALE, TIMESTAMP_SCALE_NS);
+            let duration_ms = track.duration() * 1000.0;
+            bytes_element(out, id::DURATION, &duration_ms.to_be_bytes());
+            bytes_element(out, id::MUXING_APP, b"WinGIF");
+            bytes_element(out, id::WRITING_APP, b"WinGIF");
+        });
+
+        element(out, id::TRACKS, |out| {
+            element(out, id::TRACK_ENTRY, |out| {
+                uint_element(out, id::TRACK_NUMBER, 1);
+                uint_element(out, id::TRACK_UID, 1);
+                uint_element(out, id::TRACK_TYPE, 1); // video
+                uint_element(out, id::FLAG_LACING, 0);
+                bytes_element(out, id::CODEC_ID, codec_id.as_bytes());
+                bytes_element(out, id::CODEC_PRIVATE, codec_private);
+                element(out, id::VIDEO, |out| {
+                    uint_element(out, id::PIXEL_WIDTH, track.width as u64);
+                    uint_element(out, id::PIXEL_HEIGHT, track.height as u64);
+                });
+            });
+        });
+
+        write_clusters(out, track);
+    });
+
+    fs::write(path, file).map_err(ExportError::from)
+}
+
+fn write_clusters(out: &mut Vec<u8>, track: &VideoTrack) {
+    let mut start = 0;
+    while start < track.samples.len() {
+        let cluster_ms = to_ms(track.samples[start].timestamp);
+
+        // Extend the cluster until the next keyframe past the target length,
+        // keeping relative timestamps within the signed 16-bit block range.
+        let mut end = start + 1;
+        while end < track.samples.len() {
+            let relative = to_ms(track.samples[end].timestamp) - cluster_ms;
+            if relative > i16::MAX as i64
+                || (track.samples[end].keyframe && relative >= CLUSTER_TARGET_MS)
+            {
+                break;
+            }
+            end += 1;
+        }
+
+        element(out, id::CLUSTER, |out| {
+            uint_element(out, id::TIMESTAMP, cluster_ms.max(0) as u64);
+            for sample in &track.samples[start..end] {
+                let relative = (to_ms(sample.timestamp) - cluster_ms) as i16;
+                element(out, id::SIMPLE_BLOCK, |out| {
+                    out.push(0x81); // track number 1 as a vint
+                    out.extend_from_slice(&relative.to_be_bytes());
+                    out.push(if sample.keyframe { 0x80 } else { 0x00 });
+                    out.extend_from_slice(&sample.data);
+                });
+            }
+        });
+
+        start = end;
+    }
+}
+
+fn to_ms(seconds: f64) -> i64 {
+    (seconds * 1000.0).round() as i64
+}
+
+/// Append an element whose payload is produced by `f`
+fn element(out: &mut Vec<u8>, id: u32, f: impl FnOnce(&mut Vec<u8>)) {
+    let mut payload = Vec::new();
+    f(&mut payload);
+    bytes_element(out, id, &payload);
+}
+
+fn bytes_element(out: &mut Vec<u8>, id: u32, payload: &[u8]) {
+    write_id(out, id);
+    write_size(out, payload.len() as u64);
+    out.extend_from_slice(payload);
+}
+
+fn uint_element(out: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take(7).take_while(|&&b| b == 0).count();
+    bytes_element(out, id, &bytes[skip..]);
+}
+
+/// Element IDs already carry their length marker
+fn write_id(out: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take(3).take_while(|&&b| b == 0).count();
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+/// Write an EBML variable-length size using the shortest encoding
+fn write_size(out: &mut Vec<u8>, size: u64) {
+    // All-ones is reserved for "unknown size", hence the `- 1`
+    let len = (1..=8)
+        .find(|&len| size < (1u64 << (7 * len)) - 1)
+        .unwrap_or(8);
+    let marked = size | (1u64 << (7 * len));
+    out.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::VideoSample;
+
+    fn track() -> VideoTrack {
+        let samples = (0..4)
+            .map(|i| VideoSample {
+                data: vec![i as u8; 10 + i],
+                timestamp: [0.0, 0.1, 0.25, 0.3][i],
+                keyframe: i == 0,
+            })
+            .collect();
+        VideoTrack {
+            codec: VideoCodec::Av1 { config: vec![0x81, 0x00, 0x0C, 0x00] },
+            width: 64,
+            height: 48,
+            samples,
+            frame_duration: 0.1,
+        }
+    }
+
+    /// Read a variable-length integer, keeping the length marker for IDs
+    fn vint(data: &[u8], keep_marker: bool) -> (u64, usize) {
+        let len = data[0].leading_zeros() as usize + 1;
+        let mut value = data[..len].iter().fold(0u64, |v, &b| v << 8 | b as u64);
+        if !keep_marker {
+            value &= (1u64 << (7 * len)) - 1;
+        }
+        (value, len)
+    }
+
+    /// ID and payload of each element in `data`, checking the sizes add up
+    fn elements(data: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut elements = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let (id, id_len) = vint(rest, true);
+            let (size, size_len) = vint(&rest[id_len..], false);
+            let start = id_len + size_len;
+            let end = start + size as usize;
+            assert!(end <= rest.len(), "element {id:X} overruns its parent");
+            elements.push((id as u32, &rest[start..end]));
+            rest = &rest[end..];
+        }
+        elements
+    }
+
+    fn find(data: &[u8], id: u32) -> &[u8] {
+        let found: Vec<_> = elements(data).into_iter().filter(|(i, _)| *i == id).collect();
+        assert_eq!(found.len(), 1, "expected one element {id:X}");
+        found[0].1
+    }
+
+    fn uint(data: &[u8]) -> u64 {
+        data.iter().fold(0, |v, &b| v << 8 | b as u64)
+    }
+
+    #[test]
+    fn writes_element_structure() {
+        let path = std::env::temp_dir().join(format!("wingif-webm-{}.webm", std::process::id()));
+        let track = track();
+        write(&track, &path).unwrap();
+        let file = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let top: Vec<_> = elements(&file).iter().map(|(id, _)| *id).collect();
+        assert_eq!(top, [id::EBML, id::SEGMENT]);
+        assert_eq!(find(find(&file, id::EBML), id::DOC_TYPE), b"webm");
+
+        let segment = find(&file, id::SEGMENT);
+        let info = find(segment, id::INFO);
+        assert_eq!(uint(find(info, id::TIMESTAMP_SCALE)), TIMESTAMP_SCALE_NS);
+        let duration = f64::from_be_bytes(find(info, id::DURATION).try_into().unwrap());
+        assert_eq!(duration, 400.0);
+
+        let entry = find(find(segment, id::TRACKS), id::TRACK_ENTRY);
+        assert_eq!(find(entry, id::CODEC_ID), b"V_AV1");
+        assert_eq!(find(entry, id::CODEC_PRIVATE), [0x81, 0x00, 0x0C, 0x00]);
+        let video = find(entry, id::VIDEO);
+        let size = (uint(find(video, id::PIXEL_WIDTH)), uint(find(video, id::PIXEL_HEIGHT)));
+        assert_eq!(size, (64, 48));
+
+        let cluster = find(segment, id::CLUSTER);
+        assert_eq!(uint(find(cluster, id::TIMESTAMP)), 0);
+        let blocks: Vec<_> = elements(cluster)
+            .into_iter()
+            .filter(|(id, _)| *id == id::SIMPLE_BLOCK)
+            .map(|(_, block)| block)
+            .collect();
+        assert_eq!(blocks.len(), track.samples.len());
+        let expected = track.samples.iter().zip([0, 100, 250, 300]);
+        for (block, (sample, ms)) in blocks.iter().zip(expected) {
+            assert_eq!(block[0], 0x81);
+            assert_eq!(i16::from_be_bytes([block[1], block[2]]), ms);
+            assert_eq!(block[3] & 0x80 != 0, sample.keyframe);
+            assert_eq!(&block[4..], sample.data.as_slice());
+        }
+    }
+
+    #[test]
+    fn rejects_h264() {
+        let codec = VideoCodec::Avc { sps: Vec::new(), pps: Vec::new() };
+        let track = VideoTrack { codec, ..track() };
+        let path = std::env::temp_dir().join("wingif-webm-h264.webm");
+        assert!(matches!(write(&track, &path), Err(ExportError::VideoEncode(_))));
+        assert!(!path.exists());
+    }
+}
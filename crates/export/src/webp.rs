@@ -0,0 +1,305 @@
+//! Animated WebP export using libwebp
+
+use crate::metadata::{self, EmbeddedMetadata};
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, frame_time, resize_to};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use image::RgbaImage;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use webp::{AnimEncoder, AnimFrame, WebPConfig};
+
+/// WebP export configuration
+#[derive(Debug, Clone)]
+pub struct WebPExportConfig {
+    pub output_path: PathBuf,
+    pub fps: u8,
+    /// Capture time of each frame, relative to the first; frames past its
+    /// end, or all frames when empty, follow `fps`
+    pub timestamps: Vec<Duration>,
+    /// Lossy: visual quality. Lossless: compression effort.
+    pub quality: u8,
+    pub lossless: bool,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fast: bool,
+    /// Written as an XMP chunk
+    pub metadata: EmbeddedMetadata,
+}
+
+impl Default for WebPExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15,
+            timestamps: Vec::new(),
+            quality: 90,
+            lossless: false,
+            width: None,
+            height: None,
+            fast: false,
+            metadata: EmbeddedMetadata::default(),
+        }
+    }
+}
+
+/// Animated WebP exporter
+pub struct WebPExporter {
+    config: WebPExportConfig,
+    frames: Option<Vec<RgbaImage>>,
+}
+
+impl WebPExporter {
+    /// Create a new WebP exporter
+    pub fn new(config: WebPExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            frames: None,
+        })
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        self.frames = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Add a frame to the animation
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        let frames = self.frames.as_mut()
+            .ok_or_else(|| ExportError::WebPEncode("Exporter not started".to_string()))?;
+        frames.push(image);
+        Ok(())
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        let frames = self.frames.take().unwrap_or_default();
+        Self::encode(frames, &self.config)?;
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to animated WebP
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: WebPExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let total = png_paths.len();
+        let mut frames = Vec::with_capacity(total);
+        for (i, path) in png_paths.iter().enumerate() {
+            frames.push(image::open(path)?.to_rgba8());
+
+            if let Some(ref cb) = progress {
+                cb((i + 1) as f32 / total as f32 * 0.8);
+            }
+        }
+
+        Self::encode(frames, &config)?;
+
+        if let Some(ref cb) = progress {
+            cb(1.0);
+        }
+
+        Ok(config.output_path)
+    }
+
+    fn webp_config(config: &WebPExportConfig) -> ExportResult<WebPConfig> {
+        let mut webp_config = WebPConfig::new()
+            .map_err(|_| ExportError::WebPEncode("Failed to initialize encoder config".to_string()))?;
+        webp_config.lossless = config.lossless as i32;
+        webp_config.quality = config.quality.min(100) as f32;
+        webp_config.method = if config.fast { 0 } else { 4 };
+        Ok(webp_config)
+    }
+
+    fn encode(frames: Vec<RgbaImage>, config: &WebPExportConfig) -> ExportResult<()> {
+        let first = frames.first().ok_or(ExportError::NoFrames)?;
+        let (width, height) =
+            fit_dimensions(first.width(), first.height(), config.width, config.height);
+
+        let frames: Vec<RgbaImage> = frames
+            .into_iter()
+            .map(|img| resize_to(img, width, height))
+            .collect();
+
+        let webp_config = Self::webp_config(config)?;
+
+        let millis = |index| (frame_time(&config.timestamps, config.fps, index) * 1000.0).round();
+        let mut encoder = AnimEncoder::new(width, height, &webp_config);
+        // 0 loops means loop forever
+        encoder.set_loop_count(0);
+        for (i, frame) in frames.iter().enumerate() {
+            let timestamp = millis(i) as i32;
+            encoder.add_frame(AnimFrame::from_rgba(frame.as_raw(), width, height, timestamp));
+        }
+
+        let data = encoder.try_encode()
+            .map_err(|e| ExportError::WebPEncode(format!("{:?}", e)))?;
+        let mut data = data.to_vec();
+        set_last_frame_duration(&mut data, millis(frames.len()) as u32)?;
+        if config.metadata.is_empty() {
+            fs::write(&config.output_path, &data)?;
+        } else {
+            fs::write(&config.output_path, metadata::embed_in_webp(&data, &config.metadata)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Show the last frame of the animation in `data` until `end_ms`
+///
+/// webp 0.3 closes the animation at timestamp 0, which libwebp rejects as
+/// going backwards; it then gives the last frame the average duration of
+/// the others. Single frames are written as still images and left alone.
+fn set_last_frame_duration(data: &mut [u8], end_ms: u32) -> ExportResult<()> {
+    let invalid = || ExportError::WebPEncode("malformed animation chunk".to_string());
+    let duration_at = |frame: usize| frame + 8 + 12;
+    let read_u24 = |data: &[u8], at: usize| {
+        u32::from_le_bytes([data[at], data[at + 1], data[at + 2], 0])
+    };
+
+    // ANMF payload: x, y, width - 1, height - 1, then the 24-bit duration
+    let mut elapsed = 0;
+    let mut last = None;
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let size = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        if offset + 8 + size > data.len() {
+            return Err(invalid());
+        }
+        if &data[offset..offset + 4] == b"ANMF" {
+            if size < 16 {
+                return Err(invalid());
+            }
+            if let Some(previous) = last {
+                elapsed += read_u24(data, duration_at(previous));
+            }
+            last = Some(offset);
+        }
+        offset += 8 + size + size % 2;
+    }
+
+    if let Some(last) = last {
+        let duration = end_ms.saturating_sub(elapsed).clamp(1, 0xFF_FFFF);
+        let at = duration_at(last);
+        data[at..at + 3].copy_from_slice(&duration.to_le_bytes()[..3]);
+    }
+    Ok(())
+}
+
+/// Animated WebP entry in the format registry
+pub(crate) struct WebPFormat;
+
+impl Exporter for WebPFormat {
+    fn id(&self) -> &'static str {
+        "webp"
+    }
+
+    fn name(&self) -> &'static str {
+        "WebP"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["webp"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            alpha: true,
+            variable_delays: true,
+            max_colors: None,
+            lossy: true,
+            lossless: true,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let defaults = WebPExportConfig::default();
+        let mut options = vec![
+            OptionSpec::int("quality", "Quality", 0, 100, defaults.quality as i64),
+            OptionSpec::bool("lossless", "Lossless", defaults.lossless),
+            OptionSpec::bool("fast", "Fast encoding", defaults.fast),
+        ];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let defaults = WebPExportConfig::default();
+        let options = &config.options;
+        let webp_config = WebPExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps_u8(),
+            timestamps: config.frame_timestamps(png_paths.len()),
+            quality: options.u8_or("quality", defaults.quality),
+            lossless: options.bool_or("lossless", defaults.lossless),
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+            fast: options.bool_or("fast", defaults.fast),
+            metadata: config.metadata.clone(),
+        };
+        WebPExporter::export_from_pngs(png_paths, webp_config, progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_frames;
+    use image::codecs::webp::WebPDecoder;
+    use image::AnimationDecoder;
+    use std::io::Cursor;
+
+    /// Delay of every frame in milliseconds
+    fn frame_delays(data: Vec<u8>) -> Vec<u32> {
+        let decoder = WebPDecoder::new(Cursor::new(data)).unwrap();
+        decoder
+            .into_frames()
+            .map(|frame| {
+                let (numer, denom) = frame.unwrap().delay().numer_denom_ms();
+                numer / denom
+            })
+            .collect()
+    }
+
+    fn export(frame_count: usize, fps: u8, timestamps: &[u64]) -> Vec<u32> {
+        let dir = tempfile::tempdir().unwrap();
+        let frames = write_frames(dir.path(), frame_count);
+        let config = WebPExportConfig {
+            output_path: dir.path().join("clip.webp"),
+            fps,
+            timestamps: timestamps.iter().map(|&t| Duration::from_millis(t)).collect(),
+            ..Default::default()
+        };
+        let output = WebPExporter::export_from_pngs(&frames, config, None).unwrap();
+        frame_delays(fs::read(output).unwrap())
+    }
+
+    #[test]
+    fn last_frame_keeps_its_delay() {
+        assert_eq!(export(3, 20, &[]), [50, 50, 50]);
+        // Unlike the average of the others, 200 ms
+        assert_eq!(export(3, 10, &[0, 100, 400]), [100, 300, 100]);
+        assert_eq!(export(4, 10, &[0, 250, 300]), [250, 50, 100, 100]);
+    }
+
+    #[test]
+    fn rejects_truncated_chunks() {
+        let mut data = b"RIFF\0\0\0\0WEBPANMF\xFF\0\0\0".to_vec();
+        data.extend_from_slice(&[0; 16]);
+        assert!(matches!(set_last_frame_duration(&mut data, 100), Err(ExportError::WebPEncode(_))));
+    }
+}
@@ -0,0 +1,261 @@
+//! Y4M (YUV4MPEG2) raw video export
+//!
+//! Frames are written uncompressed as they arrive, so the output can be piped
+//! straight into an external encoder.
+
+use crate::registry::{ExportConfig, Exporter, FormatCapabilities, OptionSpec};
+use crate::util::{fit_dimensions, resize_to};
+use crate::yuv::{Yuv420, Yuv444};
+use crate::{ExportError, ExportResult, ProgressCallback};
+use image::RgbaImage;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Chroma subsampling of the Y4M stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Y4mChroma {
+    /// 4:2:0, understood by practically every encoder
+    C420,
+    /// 4:4:4, keeps full color resolution for text and UI edges
+    C444,
+}
+
+/// Y4M export configuration
+#[derive(Debug, Clone)]
+pub struct Y4mExportConfig {
+    /// Output file; `-` streams to stdout
+    pub output_path: PathBuf,
+    /// Frame rate, may be fractional (e.g. frames / recording duration)
+    pub fps: f64,
+    pub chroma: Y4mChroma,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Default for Y4mExportConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::new(),
+            fps: 15.0,
+            chroma: Y4mChroma::C420,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+impl Y4mExportConfig {
+    /// Whether the stream goes to stdout instead of a file
+    pub fn is_stdout(&self) -> bool {
+        self.output_path == Path::new("-")
+    }
+}
+
+/// Express `fps` as a reduced `num:den` ratio with millihertz precision
+fn frame_rate_ratio(fps: f64) -> (u64, u64) {
+    let fps = if fps.is_finite() && fps > 0.0 { fps } else { 15.0 };
+    let num = ((fps * 1000.0).round() as u64).max(1);
+    let den = 1000;
+    let divisor = gcd(num, den);
+    (num / divisor, den / divisor)
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Y4M exporter writing to a file or stdout
+pub struct Y4mExporter {
+    config: Y4mExportConfig,
+    writer: Option<Box<dyn Write + Send>>,
+    /// Output size, known once the first frame arrives
+    size: Option<(u32, u32)>,
+    frame_count: usize,
+    progress: Option<(ProgressCallback, usize)>,
+}
+
+impl Y4mExporter {
+    /// Create a new Y4M exporter
+    pub fn new(config: Y4mExportConfig) -> ExportResult<Self> {
+        Ok(Self {
+            config,
+            writer: None,
+            size: None,
+            frame_count: 0,
+            progress: None,
+        })
+    }
+
+    /// Start the export process
+    pub fn start(&mut self) -> ExportResult<()> {
+        let writer: Box<dyn Write + Send> = if self.config.is_stdout() {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(&self.config.output_path)?))
+        };
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    /// Add a frame to the stream
+    pub fn add_frame(&mut self, image: RgbaImage) -> ExportResult<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| ExportError::Y4mEncode("Exporter not started".to_string()))?;
+
+        let (width, height) = match self.size {
+            Some(size) => size,
+            None => {
+                let size = fit_dimensions(
+                    image.width(),
+                    image.height(),
+                    self.config.width,
+                    self.config.height,
+                );
+                write_header(writer, size, &self.config)?;
+                self.size = Some(size);
+                size
+            }
+        };
+
+        let image = resize_to(image, width, height);
+        writer.write_all(b"FRAME\n")?;
+        match self.config.chroma {
+            Y4mChroma::C420 => {
+                let yuv = Yuv420::from_rgba(&image, width as usize, height as usize);
+                writer.write_all(&yuv.y)?;
+                writer.write_all(&yuv.u)?;
+                writer.write_all(&yuv.v)?;
+            }
+            Y4mChroma::C444 => {
+                let yuv = Yuv444::from_rgba(&image);
+                writer.write_all(&yuv.y)?;
+                writer.write_all(&yuv.u)?;
+                writer.write_all(&yuv.v)?;
+            }
+        }
+
+        self.frame_count += 1;
+        if let Some((ref cb, total)) = self.progress {
+            cb(self.frame_count as f32 / total.max(1) as f32);
+        }
+        Ok(())
+    }
+
+    /// Finish the export and return the output path
+    pub fn finish(mut self) -> ExportResult<PathBuf> {
+        let mut writer = self
+            .writer
+            .take()
+            .ok_or_else(|| ExportError::Y4mEncode("Exporter not started".to_string()))?;
+        if self.frame_count == 0 {
+            return Err(ExportError::NoFrames);
+        }
+
+        writer.flush()?;
+        Ok(self.config.output_path.clone())
+    }
+
+    /// Export PNG files to a Y4M stream
+    pub fn export_from_pngs(
+        png_paths: &[PathBuf],
+        config: Y4mExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        if png_paths.is_empty() {
+            return Err(ExportError::NoFrames);
+        }
+
+        let mut exporter = Self::new(config)?;
+        exporter.progress = progress.map(|cb| (cb, png_paths.len()));
+        exporter.start()?;
+
+        for path in png_paths {
+            let img = image::open(path)?.to_rgba8();
+            exporter.add_frame(img)?;
+        }
+
+        exporter.finish()
+    }
+}
+
+fn write_header(
+    writer: &mut dyn Write,
+    (width, height): (u32, u32),
+    config: &Y4mExportConfig,
+) -> io::Result<()> {
+    let (num, den) = frame_rate_ratio(config.fps);
+    // Chroma is averaged over each 2x2 block, i.e. center-sited like JPEG
+    let colorspace = match config.chroma {
+        Y4mChroma::C420 => "420jpeg XYSCSS=420JPEG",
+        Y4mChroma::C444 => "444 XYSCSS=444",
+    };
+    writeln!(
+        writer,
+        "YUV4MPEG2 W{width} H{height} F{num}:{den} Ip A1:1 C{colorspace} XCOLORRANGE=LIMITED"
+    )
+}
+
+/// Y4M entry in the format registry
+pub(crate) struct Y4mFormat;
+
+impl Exporter for Y4mFormat {
+    fn id(&self) -> &'static str {
+        "y4m"
+    }
+
+    fn name(&self) -> &'static str {
+        "Y4M raw video"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["y4m"]
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities {
+            animated: true,
+            alpha: false,
+            variable_delays: false,
+            max_colors: None,
+            lossy: false,
+            lossless: true,
+        }
+    }
+
+    fn options(&self) -> Vec<OptionSpec> {
+        let mut options = vec![OptionSpec::choice(
+            "chroma",
+            "Chroma subsampling",
+            &["420", "444"],
+            "420",
+        )];
+        options.extend(OptionSpec::size_limits());
+        options
+    }
+
+    fn encode(
+        &self,
+        png_paths: &[PathBuf],
+        config: &ExportConfig,
+        progress: Option<ProgressCallback>,
+    ) -> ExportResult<PathBuf> {
+        let options = &config.options;
+        let y4m_config = Y4mExportConfig {
+            output_path: config.output_path.clone(),
+            fps: config.fps,
+            chroma: match options.choice_or("chroma", "420") {
+                "444" => Y4mChroma::C444,
+                _ => Y4mChroma::C420,
+            },
+            width: options.dimension("width"),
+            height: options.dimension("height"),
+        };
+        Y4mExporter::export_from_pngs(png_paths, y4m_config, progress)
+    }
+}
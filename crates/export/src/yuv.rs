@@ -0,0 +1,124 @@
+//! RGBA to YUV conversion for video encoders
+//!
+//! Uses BT.709 coefficients with limited (TV) range, which is what players
+//! assume for HD content without explicit color metadata.
+
+use image::RgbaImage;
+
+/// Planar 8-bit YUV 4:2:0 image
+pub(crate) struct Yuv420 {
+    pub width: usize,
+    pub y: Vec<u8>,
+    pub u: Vec<u8>,
+    pub v: Vec<u8>,
+}
+
+impl Yuv420 {
+    /// Width of the chroma planes
+    pub fn chroma_width(&self) -> usize {
+        self.width.div_ceil(2)
+    }
+
+    /// Convert an RGBA image, padding it to `width` x `height` by repeating the
+    /// last column and row. Alpha is ignored.
+    pub fn from_rgba(img: &RgbaImage, width: usize, height: usize) -> Self {
+        let src_w = img.width() as usize;
+        let src_h = img.height() as usize;
+        let raw = img.as_raw();
+        let chroma_w = width.div_ceil(2);
+        let chroma_h = height.div_ceil(2);
+
+        let mut y = vec![0u8; width * height];
+        let mut u = vec![0u8; chroma_w * chroma_h];
+        let mut v = vec![0u8; chroma_w * chroma_h];
+
+        let pixel = |x: usize, row: usize| -> [f32; 3] {
+            let sx = x.min(src_w - 1);
+            let sy = row.min(src_h - 1);
+            let i = (sy * src_w + sx) * 4;
+            [raw[i] as f32, raw[i + 1] as f32, raw[i + 2] as f32]
+        };
+
+        for row in 0..height {
+            for x in 0..width {
+                y[row * width + x] = luma(pixel(x, row));
+            }
+        }
+
+        for cy in 0..chroma_h {
+            for cx in 0..chroma_w {
+                let mut sum = [0.0f32; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let p = pixel((cx * 2 + dx).min(width - 1), (cy * 2 + dy).min(height - 1));
+                    sum[0] += p[0];
+                    sum[1] += p[1];
+                    sum[2] += p[2];
+                }
+                let avg = [sum[0] / 4.0, sum[1] / 4.0, sum[2] / 4.0];
+                let (cb, cr) = chroma(avg);
+                u[cy * chroma_w + cx] = cb;
+                v[cy * chroma_w + cx] = cr;
+            }
+        }
+
+        Self { width, y, u, v }
+    }
+}
+
+/// Planar 8-bit YUV 4:4:4 image
+pub(crate) struct Yuv444 {
+    pub y: Vec<u8>,
+    pub u: Vec<u8>,
+    pub v: Vec<u8>,
+}
+
+impl Yuv444 {
+    /// Convert an RGBA image at its own size. Alpha is ignored.
+    pub fn from_rgba(img: &RgbaImage) -> Self {
+        let len = (img.width() * img.height()) as usize;
+        let mut y = Vec::with_capacity(len);
+        let mut u = Vec::with_capacity(len);
+        let mut v = Vec::with_capacity(len);
+
+        for p in img.pixels() {
+            let rgb = [p[0] as f32, p[1] as f32, p[2] as f32];
+            let (cb, cr) = chroma(rgb);
+            y.push(luma(rgb));
+            u.push(cb);
+            v.push(cr);
+        }
+
+        Self { y, u, v }
+    }
+}
+
+fn luma([r, g, b]: [f32; 3]) -> u8 {
+    let y = 16.0 + (0.2126 * r + 0.7152 * g + 0.0722 * b) * 219.0 / 255.0;
+    y.round().clamp(0.0, 255.0) as u8
+}
+
+fn chroma([r, g, b]: [f32; 3]) -> (u8, u8) {
+    let cb = 128.0 + (-0.1146 * r - 0.3854 * g + 0.5 * b) * 224.0 / 255.0;
+    let cr = 128.0 + (0.5 * r - 0.4542 * g - 0.0458 * b) * 224.0 / 255.0;
+    (
+        cb.round().clamp(0.0, 255.0) as u8,
+        cr.round().clamp(0.0, 255.0) as u8,
+    )
+}
+
+/// Extract the alpha channel as an 8-bit plane, padded like [`Yuv420::from_rgba`]
+pub(crate) fn alpha_plane(img: &RgbaImage, width: usize, height: usize) -> Vec<u8> {
+    let src_w = img.width() as usize;
+    let src_h = img.height() as usize;
+    let raw = img.as_raw();
+
+    let mut plane = Vec::with_capacity(width * height);
+    for row in 0..height {
+        let sy = row.min(src_h - 1);
+        for x in 0..width {
+            let sx = x.min(src_w - 1);
+            plane.push(raw[(sy * src_w + sx) * 4 + 3]);
+        }
+    }
+    plane
+}